@@ -1,41 +1,132 @@
-use nih_plug::prelude::*;
-
+mod dynamic_eq;
+mod gate;
 mod models;
 mod process;
-
-use process::CompressorSolver;
-
-use crate::compressor::{models::CompressionEmulationEnum, process::CurveType};
-
+mod sample;
+mod sidechain;
+mod transient;
+
+pub use dynamic_eq::{DynamicEq, EqShape};
+pub use gate::Gate;
+pub use process::{CompressorSolver, LowerMode};
+pub use sidechain::{DeEssShape, DeEsser, SidechainEqMode, SidechainFilter};
+pub use transient::TransientShaper;
+
+use crate::compressor::{
+    models::CompressionEmulationEnum,
+    process::{run_alpha_beta, time_coeff, CurveType},
+    sample::Sample,
+};
+
+// time constant of the adaptive makeup estimate, slow enough to follow loudness, not transients
+const ADAPTIVE_MAKEUP_MSEC: f64 = 3000.0;
+// the static makeup brings a steady signal at this level back to where it went in, louder
+// material still ends up reduced and quieter material raised
+const AUTO_MAKEUP_REF_DB: f64 = -12.0;
+
+/// Runs in `f32` in the plugin, `f64` is there for offline rendering and long time constants.
 #[derive(Debug)]
-pub struct Compressor {
+pub struct Compressor<T: Sample = f32> {
     bypass: bool,
-    curr_reduction: f32,
-    makeup_gain_db: f32,
+    curr_reduction: T,
+    hold_counter: usize,
+    makeup_gain_db: T,
+    auto_makeup: bool,
+    adaptive_makeup: bool,
+    avg_reduction: T,
+    avg_coeff: T,
     curve_type: CurveType,
-    compressor_model: CompressionEmulationEnum,
-    solver: CompressorSolver,
+    compressor_model: CompressionEmulationEnum<T>,
+    solver: CompressorSolver<T>,
 }
 
-impl Compressor {
-    pub fn new(sample_rate: f32) -> Self {
+impl<T: Sample> Compressor<T> {
+    pub fn new(sample_rate: T) -> Self {
         Compressor {
             bypass: false,
-            curr_reduction: 0.0,
-            makeup_gain_db: 0.0,
+            curr_reduction: T::zero(),
+            hold_counter: 0,
+            makeup_gain_db: T::zero(),
+            auto_makeup: false,
+            adaptive_makeup: false,
+            avg_reduction: T::zero(),
+            avg_coeff: T::zero(),
             curve_type: CurveType::default(),
             compressor_model: CompressionEmulationEnum::Ideal(models::IdealCompressor), //TODO: make this better.
             solver: CompressorSolver::new(sample_rate),
         }
     }
 
+    pub fn set_sample_rate(&mut self, sample_rate: T) {
+        self.solver.set_sample_rate(sample_rate);
+        self.avg_coeff = time_coeff(T::lit(ADAPTIVE_MAKEUP_MSEC), sample_rate);
+    }
+
+    /// call after the solver was updated, the static estimate depends on threshold/ratio/knee.
+    ///
+    /// The static estimate is the reduction a steady signal at `AUTO_MAKEUP_REF_DB` gets, the
+    /// adaptive one follows the long term average of the actual reduction.
+    pub fn update_auto_makeup(&mut self, enabled: bool, adaptive: bool) {
+        self.auto_makeup = enabled;
+        self.adaptive_makeup = adaptive;
+
+        if !enabled {
+            self.makeup_gain_db = T::zero();
+        } else if !adaptive {
+            self.makeup_gain_db = self.solver.get_ideal_reduction(T::lit(AUTO_MAKEUP_REF_DB));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.curr_reduction = T::zero();
+        self.hold_counter = 0;
+        self.avg_reduction = T::zero();
+        if self.auto_makeup && self.adaptive_makeup {
+            self.makeup_gain_db = T::zero();
+        }
+        self.solver.reset();
+        self.compressor_model.reset();
+    }
+
+    pub fn solver_mut(&mut self) -> &mut CompressorSolver<T> {
+        &mut self.solver
+    }
+
+    /// how long the current reduction takes to release once the detector went quiet
+    pub fn release_tail_msec(&self) -> T {
+        self.solver.release_tail_msec(self.curr_reduction, &self.curve_type)
+    }
+
+    pub fn hold_msec(&self) -> T {
+        self.solver.hold_msec()
+    }
+
+    //expansion only kicks in once the signal stayed under the lower threshold for the hold time
+    fn apply_hold(&mut self, lower_reduction: T) -> T {
+        if self.solver.lower_mode() != LowerMode::Expand || lower_reduction <= T::zero() {
+            self.hold_counter = self.solver.hold_samples();
+            return lower_reduction;
+        }
+
+        if self.hold_counter > 0 {
+            self.hold_counter -= 1;
+            T::zero()
+        } else {
+            lower_reduction
+        }
+    }
+
     //TODO: inline everything?
     //process sidechain
-    fn handle_reduction_calc(&mut self, sidechain_db: f32) -> f32 {
+    fn handle_reduction_calc(&mut self, sidechain_db: T) -> T {
         // step 1: get the ideal reduction needed given the current state of the filter
-        let ideal_reduction = self.solver.get_ideal_reduction(sidechain_db);
+        let lower_reduction = self.solver.get_lower_reduction(sidechain_db);
+        let ideal_reduction =
+            self.solver.get_ideal_reduction(sidechain_db) + self.apply_hold(lower_reduction);
         //println!("ideal_reduction step 1: {}", ideal_reduction);
         //step 2: apply smoothing
+        self.solver
+            .track_reduction(self.curr_reduction, ideal_reduction);
         let output_reduction;
         (output_reduction, self.curr_reduction) =
             self.solver
@@ -52,26 +143,43 @@ impl Compressor {
         return model_reduciton;
     }
 
-    pub fn process(&mut self, smp: f32, sidechain: Option<f32>) -> f32 {
-        let smp_db = util::gain_to_db_fast(smp.abs());
+    /// runs the detector only and returns the reduction in dB, for modes that apply it elsewhere
+    pub fn get_reduction(&mut self, sidechain: T) -> T {
+        if self.bypass {
+            return T::zero();
+        }
+
+        self.handle_reduction_calc(sidechain.abs().gain_to_db())
+    }
+
+    pub fn process(&mut self, smp: T, sidechain: Option<T>) -> T {
+        let smp_db = smp.abs().gain_to_db();
 
         if self.bypass {
             return smp;
         }
 
         let sidechain_db = match sidechain {
-            Some(x) => util::gain_to_db_fast(x.abs()),
+            Some(x) => x.abs().gain_to_db(),
             None => smp_db,
         };
 
         let reduction_db = self.handle_reduction_calc(sidechain_db);
-        smp * util::db_to_gain_fast(-reduction_db + self.makeup_gain_db)
+
+        if self.auto_makeup && self.adaptive_makeup {
+            self.avg_reduction = run_alpha_beta(self.avg_coeff, self.avg_reduction, reduction_db);
+            self.makeup_gain_db = self.avg_reduction;
+        }
+
+        smp * (-reduction_db + self.makeup_gain_db).db_to_gain()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Compressor;
+    use super::{
+        sample::Sample, Compressor, Gate, SidechainEqMode, SidechainFilter, TransientShaper,
+    };
     use anyhow::Error;
     use plotters::prelude::*;
     use wavers::Wav;
@@ -196,6 +304,16 @@ mod tests {
     //test3 - long note example
     //test4 - sidechain
     //test5 - models
+    //test6 - upward compression and expansion
+    //test7 - auto makeup
+    //test8 - auto release step response
+    //test10 - f32 and f64 agree
+    //test11 - release tails stay out of the subnormals
+    //test12 - gate hysteresis and range
+    //test13 - gate hold
+    //test14 - sidechain hpf and lpf
+    //test15 - transient shaper attack and sustain
+    //test16 - release tail follows the curve
 
     #[test]
     fn run_compressor_ex001() {
@@ -326,4 +444,436 @@ mod tests {
         }
         testresults.draw_plot().unwrap();
     }
+
+    #[test]
+    fn run_compressor_ex006() {
+        let mut comp: Compressor = Compressor::new(44100.0);
+
+        comp.solver.threshold = -6.0;
+        comp.solver.update_ratio(4.0);
+        comp.solver.lower_threshold = -40.0;
+        comp.solver.update_lower_ratio(2.0);
+        comp.solver.update_max_gain(12.0);
+        comp.solver.update_range(30.0);
+
+        // upward: 10dB under the lower threshold at 2:1 gets 5dB back, capped at the max gain
+        comp.solver.update_lower_mode(super::LowerMode::Upward);
+        assert_eq!(comp.solver.get_lower_reduction(-50.0), -5.0);
+        assert_eq!(comp.solver.get_lower_reduction(-99.0), -12.0);
+        assert_eq!(comp.solver.get_lower_reduction(-20.0), 0.0);
+
+        // expand: 10dB under the lower threshold at 1:2 drops another 10dB, limited by the range
+        comp.solver.update_lower_mode(super::LowerMode::Expand);
+        assert_eq!(comp.solver.get_lower_reduction(-50.0), 10.0);
+        assert_eq!(comp.solver.get_lower_reduction(-99.0), 30.0);
+
+        // the upper threshold keeps working alongside
+        assert_eq!(comp.solver.get_ideal_reduction(-2.0), 3.0);
+
+        // hold delays the expansion after the signal drops
+        comp.solver.update_attack(0.0);
+        comp.solver.update_release(0.0);
+        comp.solver.update_hold(1.0);
+        let hold = comp.solver.hold_samples();
+        assert_eq!(hold, 44);
+
+        comp.process(0.5, None);
+        for _ in 0..hold {
+            comp.process(0.001, None);
+            assert_eq!(comp.curr_reduction, 0.0);
+        }
+        comp.process(0.001, None);
+        assert!(comp.curr_reduction > 0.0);
+    }
+
+    #[test]
+    fn run_compressor_ex007() {
+        let mut comp: Compressor = Compressor::new(44100.0);
+
+        comp.solver.threshold = -20.0;
+        comp.solver.update_ratio(4.0);
+
+        // -12dB gets 6dB of reduction, all of it comes back
+        comp.update_auto_makeup(true, false);
+        assert_eq!(comp.makeup_gain_db, 6.0);
+
+        // so a steady signal at the reference level leaves at the level it came in
+        comp.solver.update_attack(0.0);
+        comp.solver.update_release(0.0);
+        let reference = (-12.0_f32).db_to_gain();
+        let out = comp.process(reference, None);
+        assert!((out.gain_to_db() + 12.0).abs() < 0.001, "{out}");
+
+        // a threshold above the reference needs no makeup
+        comp.solver.threshold = -6.0;
+        comp.update_auto_makeup(true, false);
+        assert_eq!(comp.makeup_gain_db, 0.0);
+
+        comp.solver.threshold = -20.0;
+        comp.update_auto_makeup(false, false);
+        assert_eq!(comp.makeup_gain_db, 0.0);
+        comp.reset();
+
+        // a steady signal ends up at its static reduction fully made up
+        comp.set_sample_rate(44100.0);
+        comp.solver.update_attack(1.0);
+        comp.solver.update_release(10.0);
+        comp.update_auto_makeup(true, true);
+        for _ in 0..(44100 * 10) {
+            comp.process(0.5, None);
+        }
+        assert!((comp.makeup_gain_db - comp.curr_reduction).abs() < 0.1);
+    }
+
+    // samples until the reduction falls to 10% of where it was when the input dropped
+    fn measure_release(comp: &mut Compressor, burst: &[f32]) -> usize {
+        for sample in burst {
+            comp.process(*sample, None);
+        }
+
+        let start_reduction = comp.curr_reduction;
+        let mut released = 0;
+        while comp.curr_reduction > start_reduction * 0.1 {
+            comp.process(0.0001, None);
+            released += 1;
+        }
+
+        released
+    }
+
+    fn auto_release_compressor<T: Sample>() -> Compressor<T> {
+        let mut comp = Compressor::new(T::lit(44100.0));
+
+        comp.solver.threshold = T::lit(-40.0);
+        comp.solver.update_ratio(T::lit(4.0));
+        comp.curve_type = super::process::CurveType::LogSmoothBranching;
+        comp.solver.update_attack(T::lit(1.0));
+        comp.solver.update_release(T::lit(100.0));
+        comp.solver.update_auto_release(true);
+
+        comp
+    }
+
+    #[test]
+    fn run_compressor_ex008() {
+        let samples = get_wave_stream("testfiles/tambourine_studio_short_loop_2_cF2.wav");
+        let peak = samples.iter().fold(0.0_f32, |acc, x| acc.max(x.abs()));
+        let peak_idx = samples.iter().position(|x| x.abs() == peak).unwrap();
+
+        // a single hit releases fast
+        let mut comp = auto_release_compressor();
+        let transient = &samples[peak_idx.saturating_sub(220)..peak_idx + 220];
+        let transient_release = measure_release(&mut comp, transient);
+
+        // the same material held for a couple of seconds releases slowly
+        let mut comp = auto_release_compressor();
+        let sustained: Vec<f32> = samples.iter().cycle().take(44100 * 2).copied().collect();
+        let sustained_release = measure_release(&mut comp, &sustained);
+
+        assert!(transient_release < 44100 / 5);
+        assert!(sustained_release > transient_release * 3);
+
+        // and without auto release both use the fixed release time
+        let mut comp = auto_release_compressor();
+        comp.solver.update_auto_release(false);
+        let fixed_transient = measure_release(&mut comp, transient);
+        let mut comp = auto_release_compressor();
+        comp.solver.update_auto_release(false);
+        let fixed_sustained = measure_release(&mut comp, &sustained);
+
+        assert!(fixed_transient.abs_diff(fixed_sustained) < 44100 / 100);
+    }
+
+    #[test]
+    fn run_compressor_ex009() {
+        let mut comp = auto_release_compressor();
+
+        let samples = get_wave_stream("testfiles/tambourine_studio_short_loop_2_cF2.wav");
+
+        let mut testresults = Results {
+            filename: "tmp/ex009.png",
+            ..Default::default()
+        };
+
+        for (idx, sample) in samples.iter().enumerate() {
+            let smp = comp.process(*sample, None);
+
+            assert!(smp.is_finite());
+            assert!(smp.abs() <= sample.abs() + f32::EPSILON);
+
+            testresults.samples.push(ResultSample {
+                idx,
+                input: *sample,
+                output: smp,
+                reduciton: -comp.curr_reduction,
+            });
+        }
+        testresults.draw_plot_db().unwrap();
+    }
+
+    fn precision_compressor<T: Sample>() -> Compressor<T> {
+        let mut comp = Compressor::new(T::lit(44100.0));
+
+        comp.set_sample_rate(T::lit(44100.0));
+        comp.solver.threshold = T::lit(-40.0);
+        comp.solver.update_ratio(T::lit(4.0));
+        comp.solver.lower_threshold = T::lit(-60.0);
+        comp.solver.update_lower_mode(super::LowerMode::Expand);
+        comp.solver.update_lower_ratio(T::lit(2.0));
+        comp.solver.update_range(T::lit(20.0));
+        comp.curve_type = super::process::CurveType::LogSmoothBranching;
+        comp.solver.update_attack(T::lit(5.0));
+        comp.solver.update_release(T::lit(100.0));
+        comp.solver.update_auto_release(true);
+        comp.update_auto_makeup(true, true);
+
+        comp
+    }
+
+    #[test]
+    fn run_compressor_ex010() {
+        let samples = get_wave_stream("testfiles/tambourine_studio_short_loop_2_cF2.wav");
+
+        let mut comp32 = precision_compressor::<f32>();
+        let mut comp64 = precision_compressor::<f64>();
+
+        for sample in samples.iter().cycle().take(44100 * 3) {
+            let out32 = comp32.process(*sample, None);
+            let out64 = comp64.process(*sample as f64, None);
+
+            assert!((comp32.curr_reduction as f64 - comp64.curr_reduction).abs() < 0.05);
+            assert!((out32 as f64 - out64).abs() <= sample.abs() as f64 * 0.01 + 1e-6);
+        }
+        assert!((comp32.makeup_gain_db as f64 - comp64.makeup_gain_db).abs() < 0.05);
+    }
+
+    #[test]
+    fn run_compressor_ex011() {
+        let mut comp = auto_release_compressor::<f32>();
+        let mut comp64 = auto_release_compressor::<f64>();
+
+        for _ in 0..4410 {
+            comp.process(0.5, None);
+            comp64.process(0.5, None);
+        }
+        assert!(comp.curr_reduction > 0.0);
+
+        // the input stops dead, a release decaying on its own would end up subnormal
+        for _ in 0..(44100 * 60) {
+            comp.process(0.0, None);
+            comp64.process(0.0, None);
+            assert!(!comp.curr_reduction.is_subnormal());
+            assert!(!comp64.curr_reduction.is_subnormal());
+        }
+        assert_eq!(comp.curr_reduction, 0.0);
+        assert_eq!(comp64.curr_reduction, 0.0);
+    }
+
+    // instant ballistics, so every sample shows the gate's state
+    fn instant_gate(hold_msec: f32) -> Gate {
+        let mut gate = Gate::new(44100.0);
+
+        gate.enabled = true;
+        gate.threshold = -40.0;
+        gate.hysteresis = 6.0;
+        gate.update_range(30.0);
+        gate.update_attack(0.0);
+        gate.update_release(0.0);
+        gate.update_hold(hold_msec);
+
+        gate
+    }
+
+    // gain the gate applies to a sample at `level_db`, in dB
+    fn gate_gain_db(gate: &mut Gate, level_db: f32) -> f32 {
+        let smp = nih_plug::util::db_to_gain(level_db);
+        nih_plug::util::gain_to_db(gate.process(smp, None) / smp)
+    }
+
+    #[test]
+    fn run_compressor_ex012() {
+        let mut gate = instant_gate(0.0);
+
+        // inside the hysteresis an open gate stays open
+        assert!(gate_gain_db(&mut gate, -30.0).abs() < 0.1);
+        assert!(gate_gain_db(&mut gate, -43.0).abs() < 0.1);
+
+        // under it the gate closes by the range
+        assert!((gate_gain_db(&mut gate, -47.0) + 30.0).abs() < 0.1);
+
+        // and a closed gate needs the full threshold to open again
+        assert!((gate_gain_db(&mut gate, -43.0) + 30.0).abs() < 0.1);
+        assert!((gate_gain_db(&mut gate, -41.0) + 30.0).abs() < 0.1);
+        assert!(gate_gain_db(&mut gate, -39.0).abs() < 0.1);
+
+        gate.update_range(10.0);
+        assert!((gate_gain_db(&mut gate, -60.0) + 10.0).abs() < 0.1);
+
+        // a disabled gate passes everything
+        gate.enabled = false;
+        assert!(gate_gain_db(&mut gate, -60.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn run_compressor_ex013() {
+        // 10 ms at 44.1 kHz
+        let mut gate = instant_gate(10.0);
+        let hold = 441;
+
+        gate_gain_db(&mut gate, -30.0);
+        for _ in 0..hold {
+            assert!(gate_gain_db(&mut gate, -60.0).abs() < 0.1);
+        }
+        assert!((gate_gain_db(&mut gate, -60.0) + 30.0).abs() < 0.1);
+
+        // signal coming back inside the hold starts it over
+        gate_gain_db(&mut gate, -30.0);
+        for _ in 0..hold / 2 {
+            gate_gain_db(&mut gate, -60.0);
+        }
+        gate_gain_db(&mut gate, -30.0);
+        for _ in 0..hold {
+            assert!(gate_gain_db(&mut gate, -60.0).abs() < 0.1);
+        }
+        assert!((gate_gain_db(&mut gate, -60.0) + 30.0).abs() < 0.1);
+    }
+
+    // steady state peak of a full scale sine at `freq` through the filter, in dB
+    fn sidechain_peak_db(filter: &mut SidechainFilter, freq: f32) -> f32 {
+        filter.reset();
+
+        let mut peak = 0.0_f32;
+        for idx in 0..48000 {
+            let smp = (std::f32::consts::TAU * (idx as f32 * freq / 48000.0).fract()).sin();
+            let out = filter.process(smp);
+            if idx >= 24000 {
+                peak = peak.max(out.abs());
+            }
+        }
+
+        nih_plug::util::gain_to_db(peak)
+    }
+
+    #[test]
+    fn run_compressor_ex014() {
+        let corner = 1000.0 / 48000.0;
+        let mut filter = SidechainFilter::default();
+
+        // nothing enabled, the key passes untouched
+        assert!(sidechain_peak_db(&mut filter, 100.0).abs() < 0.1);
+
+        // second order butterworth, 40 dB down a decade away and 3 dB down at the corner
+        filter.update(Some(corner), None, SidechainEqMode::Off, 0.0, 0.0, 1.0);
+        assert!(sidechain_peak_db(&mut filter, 100.0) < -35.0);
+        assert!((sidechain_peak_db(&mut filter, 1000.0) + 3.0).abs() < 0.3);
+        assert!(sidechain_peak_db(&mut filter, 10000.0).abs() < 0.3);
+
+        filter.update(None, Some(corner), SidechainEqMode::Off, 0.0, 0.0, 1.0);
+        assert!(sidechain_peak_db(&mut filter, 100.0).abs() < 0.3);
+        assert!((sidechain_peak_db(&mut filter, 1000.0) + 3.0).abs() < 0.3);
+        assert!(sidechain_peak_db(&mut filter, 10000.0) < -35.0);
+
+        // a compressor keyed through the hpf ignores the lows it would otherwise duck on
+        let reduction = |filter: Option<&mut SidechainFilter>| {
+            let mut comp: Compressor = Compressor::new(48000.0);
+            comp.solver.threshold = -20.0;
+            comp.solver.update_ratio(4.0);
+            comp.solver.update_attack(1.0);
+            comp.solver.update_release(10.0);
+
+            let mut filter = filter;
+            let mut max_reduction = 0.0_f32;
+            for idx in 0..24000 {
+                let smp = 0.5 * (std::f32::consts::TAU * (idx as f32 / 480.0).fract()).sin();
+                let key = match filter.as_deref_mut() {
+                    Some(filter) => filter.process(smp),
+                    None => smp,
+                };
+                comp.process(smp, Some(key));
+                if idx >= 12000 {
+                    max_reduction = max_reduction.max(comp.curr_reduction);
+                }
+            }
+
+            max_reduction
+        };
+        filter.update(Some(corner), None, SidechainEqMode::Off, 0.0, 0.0, 1.0);
+        filter.reset();
+        assert!(reduction(None) > 5.0);
+        assert!(reduction(Some(&mut filter)) < 0.1);
+    }
+
+    // gain in dB 2 ms into a step up, at the end of the held level and 20 ms into the drop
+    fn transient_gains_db(attack_gain_db: f32, sustain_gain_db: f32) -> [f32; 3] {
+        let mut shaper = TransientShaper::default();
+        shaper.update(48000.0, attack_gain_db, sustain_gain_db);
+
+        let mut gains = [0.0; 3];
+        for idx in 0..19200 {
+            let level = if (4800..14400).contains(&idx) { 0.5 } else { 0.05 };
+            let gain_db = (shaper.process(level, level) / level).gain_to_db();
+            match idx {
+                4896 => gains[0] = gain_db,
+                14399 => gains[1] = gain_db,
+                15360 => gains[2] = gain_db,
+                _ => (),
+            }
+        }
+
+        gains
+    }
+
+    #[test]
+    fn run_compressor_ex015() {
+        // the attack gain acts on the onset only, and in the direction it's set
+        let [onset, held, decay] = transient_gains_db(6.0, 0.0);
+        assert!(onset > 3.0, "{onset}");
+        assert!(held.abs() < 0.5 && decay.abs() < 0.5, "{held} {decay}");
+        let [onset, held, _] = transient_gains_db(-6.0, 0.0);
+        assert!(onset < -3.0, "{onset}");
+        assert!(held.abs() < 0.5, "{held}");
+
+        // the sustain gain acts on the decay only
+        let [onset, held, decay] = transient_gains_db(0.0, 6.0);
+        assert!(decay > 3.0, "{decay}");
+        assert!(onset.abs() < 0.5 && held.abs() < 0.5, "{onset} {held}");
+        let [_, _, decay] = transient_gains_db(0.0, -6.0);
+        assert!(decay < -3.0, "{decay}");
+    }
+
+    #[test]
+    fn run_compressor_ex016() {
+        for curve_type in [
+            super::process::CurveType::LogLin,
+            super::process::CurveType::LogSmoothBranching,
+        ] {
+            let mut comp = Compressor::new(44100.0);
+
+            comp.solver.threshold = -40.0;
+            comp.solver.update_ratio(20.0);
+            comp.curve_type = curve_type;
+            comp.solver.update_attack(1.0);
+            comp.solver.update_release(100.0);
+
+            for _ in 0..4410 {
+                comp.process(1.0, None);
+            }
+            assert!(comp.curr_reduction > 30.0, "{}", comp.curr_reduction);
+
+            // still releasing most of the way through the tail, settled at its end
+            let tail = (comp.release_tail_msec() * 44.1).ceil() as usize;
+            for idx in 0..tail {
+                if idx == tail * 4 / 5 {
+                    assert!(comp.curr_reduction > 0.1, "{:?}", comp.curve_type);
+                }
+                comp.process(0.0, None);
+            }
+            assert!(
+                comp.curr_reduction <= 0.1,
+                "{:?}: {}",
+                comp.curve_type,
+                comp.curr_reduction
+            );
+        }
+    }
 }
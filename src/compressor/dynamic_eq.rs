@@ -0,0 +1,77 @@
+use cute_dsp::filters::Biquad;
+use nih_plug::prelude::Enum;
+
+// redesigning the biquad every sample is wasteful, the reduction is smooth enough for this
+const UPDATE_INTERVAL: usize = 16;
+
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EqShape {
+    #[default]
+    Bell,
+    #[name = "Low Shelf"]
+    LowShelf,
+    #[name = "High Shelf"]
+    HighShelf,
+}
+
+/// EQ band whose gain follows the compressor's reduction instead of scaling a crossover band.
+///
+/// A negative range cuts by up to that much as the detector goes over threshold, a positive
+/// range boosts instead.
+pub struct DynamicEq {
+    freq: f32,
+    q: f32,
+    shape: EqShape,
+    range_db: f32,
+    update_counter: usize,
+    filt: Biquad<f32>,
+}
+
+impl Default for DynamicEq {
+    fn default() -> Self {
+        Self {
+            freq: 0.0,
+            q: 1.0,
+            shape: EqShape::default(),
+            range_db: 0.0,
+            update_counter: 0,
+            filt: Biquad::<f32>::new(true),
+        }
+    }
+}
+
+impl DynamicEq {
+    /// `freq` is normalised to the sample rate.
+    pub fn update(&mut self, freq: f32, q: f32, shape: EqShape, range_db: f32) {
+        self.freq = freq;
+        self.q = q;
+        self.shape = shape;
+        self.range_db = range_db;
+    }
+
+    pub fn reset(&mut self) {
+        self.update_counter = 0;
+        self.filt.reset();
+    }
+
+    fn design(&mut self, gain_db: f32) {
+        match self.shape {
+            EqShape::Bell => self.filt.peak_db(self.freq, gain_db, self.q),
+            EqShape::LowShelf => self.filt.low_shelf_db(self.freq, gain_db, self.q),
+            EqShape::HighShelf => self.filt.high_shelf_db(self.freq, gain_db, self.q),
+        };
+    }
+
+    pub fn process(&mut self, smp: f32, reduction_db: f32) -> f32 {
+        if self.update_counter == 0 {
+            let gain_db = reduction_db
+                .clamp(0.0, self.range_db.abs())
+                .copysign(self.range_db);
+            self.design(gain_db);
+            self.update_counter = UPDATE_INTERVAL;
+        }
+        self.update_counter -= 1;
+
+        self.filt.process(smp)
+    }
+}
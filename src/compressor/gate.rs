@@ -0,0 +1,98 @@
+use nih_plug::util;
+
+use super::process::{CompressorSolver, CurveType};
+
+/// Downward expander / noise gate that sits in front of the compressor of a band.
+///
+/// The ballistics reuse the `CompressorSolver` coefficients. Since the gate's reduction grows
+/// when it closes, the solver's attack slot holds the gate release and vice versa.
+#[derive(Debug)]
+pub struct Gate {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub hysteresis: f32,
+    range_db: f32,
+    open: bool,
+    hold_counter: usize,
+    curr_reduction: f32,
+    solver: CompressorSolver,
+}
+
+impl Gate {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            enabled: false,
+            threshold: -60.0,
+            hysteresis: 0.0,
+            range_db: 0.0,
+            open: true,
+            hold_counter: 0,
+            curr_reduction: 0.0,
+            solver: CompressorSolver::new(sample_rate),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.solver.set_sample_rate(sample_rate);
+    }
+
+    pub fn reset(&mut self) {
+        self.open = true;
+        self.hold_counter = 0;
+        self.curr_reduction = 0.0;
+        self.solver.reset();
+    }
+
+    pub fn update_range(&mut self, range_db: f32) {
+        self.range_db = range_db.max(0.0);
+    }
+
+    pub fn update_attack(&mut self, attack_msec: f32) {
+        self.solver.update_release(attack_msec);
+    }
+
+    pub fn update_release(&mut self, release_msec: f32) {
+        self.solver.update_attack(release_msec);
+    }
+
+    pub fn update_hold(&mut self, hold_msec: f32) {
+        self.solver.update_hold(hold_msec);
+    }
+
+    pub fn hold_msec(&self) -> f32 {
+        self.solver.hold_msec()
+    }
+
+    pub fn release_msec(&self) -> f32 {
+        self.solver.attack_msec
+    }
+
+    fn update_state(&mut self, level_db: f32) {
+        if level_db >= self.threshold {
+            self.open = true;
+            self.hold_counter = self.solver.hold_samples();
+        } else if level_db < self.threshold - self.hysteresis {
+            if self.hold_counter > 0 {
+                self.hold_counter -= 1;
+            } else {
+                self.open = false;
+            }
+        }
+    }
+
+    pub fn process(&mut self, smp: f32, sidechain: Option<f32>) -> f32 {
+        if !self.enabled {
+            return smp;
+        }
+
+        let level_db = util::gain_to_db_fast(sidechain.unwrap_or(smp).abs());
+        self.update_state(level_db);
+
+        let target = if self.open { 0.0 } else { self.range_db };
+        (_, self.curr_reduction) =
+            self.solver
+                .apply_curve(self.curr_reduction, target, &CurveType::LogSmoothBranching);
+
+        smp * util::db_to_gain_fast(-self.curr_reduction)
+    }
+}
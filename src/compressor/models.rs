@@ -2,67 +2,80 @@
 use std::fmt::Debug;
 
 use super::process::run_alpha_beta;
+use super::sample::Sample;
 
 
 #[derive(Debug)]
-pub enum CompressionEmulationEnum{
+pub enum CompressionEmulationEnum<T: Sample>{
    Ideal(IdealCompressor), //peak
-   Optical(OpticalCompressor), 
-   VCA(VCACompressor), //rms
+   Optical(OpticalCompressor<T>), 
+   VCA(VCACompressor<T>), //rms
    
 }
 
-impl CompressionEmulationEnum{
-    pub fn get_gain_reduction(&mut self, new_reduction: f32, ideal_reduction: f32) -> f32 {
+impl<T: Sample> CompressionEmulationEnum<T>{
+    pub fn get_gain_reduction(&mut self, new_reduction: T, ideal_reduction: T) -> T {
         match self {
             CompressionEmulationEnum::Ideal(x) => x.get_gain_reduction(new_reduction, ideal_reduction),
             CompressionEmulationEnum::Optical(x) => x.get_gain_reduction(new_reduction, ideal_reduction),
             CompressionEmulationEnum::VCA(x) => x.get_gain_reduction(new_reduction, ideal_reduction)
         }
     }
+
+    pub fn reset(&mut self) {
+        match self {
+            CompressionEmulationEnum::Ideal(x) => x.reset(),
+            CompressionEmulationEnum::Optical(x) => x.reset(),
+            CompressionEmulationEnum::VCA(x) => x.reset()
+        }
+    }
 }
 
 
-pub trait CompressionModel: Debug {
-    fn get_gain_reduction(&mut self, new_reduction: f32, ideal_reduction: f32) -> f32;
+pub trait CompressionModel<T: Sample>: Debug {
+    fn get_gain_reduction(&mut self, new_reduction: T, ideal_reduction: T) -> T;
+    /// clear any envelope state so a new render starts from scratch
+    fn reset(&mut self);
 }
 
 #[derive(Debug,Default)]
 pub struct IdealCompressor;
 
-impl CompressionModel for IdealCompressor {
-    fn get_gain_reduction(&mut self, new_reduction: f32, _ideal_reduction: f32) -> f32 {
+impl<T: Sample> CompressionModel<T> for IdealCompressor {
+    fn get_gain_reduction(&mut self, new_reduction: T, _ideal_reduction: T) -> T {
         new_reduction
     }
+
+    fn reset(&mut self) {}
 }
 
 
 
 
 #[derive(Debug)]
-pub struct OpticalCompressor {
+pub struct OpticalCompressor<T: Sample> {
     coeffs_per_step: usize,
     total_coeffs: usize,
-    current_reduction: f32,
-    attack_coeffs: Vec<f32>,
-    release_coeffs: Vec<f32>,
-    limit: f32,
+    current_reduction: T,
+    attack_coeffs: Vec<T>,
+    release_coeffs: Vec<T>,
+    limit: T,
 }
 
-impl OpticalCompressor {
-    pub fn new(sample_rate: f32, steps: usize, coeffs_per_step: usize) -> Self {
+impl<T: Sample> OpticalCompressor<T> {
+    pub fn new(sample_rate: T, steps: usize, coeffs_per_step: usize) -> Self {
         let total_coeffs = steps * coeffs_per_step;
-        let mut attack_coeffs = vec![0.0_f32; total_coeffs];
-        let mut release_coeffs = vec![0.0_f32; total_coeffs];
+        let mut attack_coeffs = vec![T::zero(); total_coeffs];
+        let mut release_coeffs = vec![T::zero(); total_coeffs];
 
         for idx in 0..total_coeffs {
-            let step_db = idx as f32 / coeffs_per_step as f32;
-            let resistance = 480.0 / (3.0 + step_db);
-            let attack_rate = resistance / 10.0; //TODO: this needs to be a parameter
+            let step_db = T::lit(idx as f64 / coeffs_per_step as f64);
+            let resistance = T::lit(480.0) / (T::lit(3.0) + step_db);
+            let attack_rate = resistance / T::lit(10.0); //TODO: this needs to be a parameter
             let release_rate = resistance; //TODO: this needs to be a parameter
 
-            attack_coeffs[idx] = f32::exp(0.27_f32.ln() / (sample_rate * attack_rate / 1000.0));
-            release_coeffs[idx] = f32::exp(0.27_f32.ln() / (sample_rate * release_rate / 1000.0));
+            attack_coeffs[idx] = (T::lit(0.27).ln() / (sample_rate * attack_rate / T::lit(1000.0))).exp();
+            release_coeffs[idx] = (T::lit(0.27).ln() / (sample_rate * release_rate / T::lit(1000.0))).exp();
         }
 
         Self {
@@ -70,16 +83,16 @@ impl OpticalCompressor {
             release_coeffs,
             coeffs_per_step,
             total_coeffs,
-            limit: 24.0,
-            current_reduction: 0.0,
+            limit: T::lit(24.0),
+            current_reduction: T::zero(),
         }
     }
 }
 
-impl CompressionModel for OpticalCompressor {
-    fn get_gain_reduction(&mut self, new_reduction: f32, ideal_reduction: f32) -> f32 {
+impl<T: Sample> CompressionModel<T> for OpticalCompressor<T> {
+    fn get_gain_reduction(&mut self, new_reduction: T, ideal_reduction: T) -> T {
         let old_reduction = self.current_reduction;
-        let ncoeff = (new_reduction * (self.coeffs_per_step as f32)) as isize;
+        let ncoeff = (new_reduction * T::lit(self.coeffs_per_step as f64)).to_isize().unwrap_or(0);
         let ncoeff = ncoeff.clamp(0, (self.total_coeffs - 1) as isize) as usize;
 
         let gain_reduction = if new_reduction > self.current_reduction {
@@ -91,24 +104,28 @@ impl CompressionModel for OpticalCompressor {
         if gain_reduction < ideal_reduction {
             let diff = ideal_reduction - gain_reduction;
 
-            ideal_reduction - (self.limit - (self.limit / (1.0 + (diff / self.limit))))
+            ideal_reduction - (self.limit - (self.limit / (T::one() + (diff / self.limit))))
         } else {
             gain_reduction
         }
     }
+
+    fn reset(&mut self) {
+        self.current_reduction = T::zero();
+    }
 }
 
 
 #[derive(Debug)]
-pub struct VCACompressor{
-    current_reduction_sq:f32,
-    window_size_msec:f32
+pub struct VCACompressor<T: Sample>{
+    current_reduction_sq:T,
+    window_size_msec:T
 }
     
-impl VCACompressor{
-   fn apply_rms_filter(&self,input_level:f32) -> (f32,f32){
+impl<T: Sample> VCACompressor<T>{
+   fn apply_rms_filter(&self,input_level:T) -> (T,T){
         
-        if self.window_size_msec == 0.0 {
+        if self.window_size_msec == T::zero() {
             return (self.current_reduction_sq,input_level)
         }
 
@@ -125,12 +142,16 @@ impl VCACompressor{
 
 
 
-impl CompressionModel for VCACompressor {
-    fn get_gain_reduction(&mut self, _new_reduction: f32, ideal_reduction: f32) -> f32 {
+impl<T: Sample> CompressionModel<T> for VCACompressor<T> {
+    fn get_gain_reduction(&mut self, _new_reduction: T, ideal_reduction: T) -> T {
         let (filtered_sq,filtered) = self.apply_rms_filter(ideal_reduction);
 
         self.current_reduction_sq = filtered_sq;
 
         return filtered
     }
+
+    fn reset(&mut self) {
+        self.current_reduction_sq = T::zero();
+    }
 }
\ No newline at end of file
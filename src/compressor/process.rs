@@ -1,8 +1,34 @@
 use std::cmp::Ordering;
 
+use nih_plug::prelude::Enum;
+
+use super::sample::Sample;
+
+// auto release time constants, fast for transients and slow for sustained reduction
+const AUTO_RELEASE_FAST_MSEC: f64 = 50.0;
+const AUTO_RELEASE_SLOW_MSEC: f64 = 1500.0;
+// after holding reduction this long the slow release has half the say
+const AUTO_RELEASE_BLEND_MSEC: f64 = 250.0;
+// reduction under this counts as fully released
+const AUTO_RELEASE_ACTIVE_DB: f64 = 0.5;
+// an exponential release never quite gets there, this close counts as settled
+const RELEASE_SETTLED_DB: f64 = 0.1;
+
+// every envelope follower runs through here, the guard stops long release tails from decaying
+// into subnormals when the cpu doesn't flush them
 #[inline]
-pub fn run_alpha_beta(coeff: f32, prev_val: f32, new_val: f32) -> f32 {
-    return coeff * prev_val + (1.0 - coeff) * new_val;
+pub fn run_alpha_beta<T: Sample>(coeff: T, prev_val: T, new_val: T) -> T {
+    return (coeff * prev_val + (T::one() - coeff) * new_val).flush_denormal();
+}
+
+// one pole coefficient, gets within 10% of the target after `msec`
+pub fn time_coeff<T: Sample>(msec: T, sample_rate: T) -> T {
+    (T::lit(0.10).ln() / (msec * sample_rate / T::lit(1000.0))).exp()
+}
+
+// linear release, 10 dB per `msec`
+fn lin_coeff<T: Sample>(msec: T, sample_rate: T) -> T {
+    T::lit(10.0) / (msec * sample_rate / T::lit(1000.0))
 }
 
 #[derive(Default, Debug)]
@@ -13,74 +39,244 @@ pub enum CurveType {
     LogSmoothBranching,
 }
 
+/// What happens to material below the lower threshold.
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LowerMode {
+    #[default]
+    Off,
+    /// Raise quiet material towards the lower threshold, capped at the max gain.
+    Upward,
+    /// Push quiet material further down, limited by the range (a gate at high ratios).
+    Expand,
+}
+
 #[derive(Default, Debug)]
-pub struct CompressorSolver {
-    sample_rate: f32,
-    pub threshold: f32,
-    ratio: f32,
-    knee_width: f32,
-    knee_width_x0_5: f32,
-    knee_width_x2: f32,
-    pub attack_msec: f32,
-    pub release_msec: f32,
-    attack_coeff: f32,
-    release_coeff_lin: f32,
-    release_coeff: f32,
+pub struct CompressorSolver<T: Sample = f32> {
+    sample_rate: T,
+    pub threshold: T,
+    ratio: T,
+    knee_width: T,
+    knee_width_x0_5: T,
+    knee_width_x2: T,
+    pub attack_msec: T,
+    pub release_msec: T,
+    attack_coeff: T,
+    release_coeff_lin: T,
+    release_coeff: T,
+    lower_mode: LowerMode,
+    pub lower_threshold: T,
+    lower_ratio: T,
+    max_gain_db: T,
+    range_db: T,
+    hold_samples: usize,
+    auto_release: bool,
+    release_fast_coeff: T,
+    release_fast_coeff_lin: T,
+    release_slow_coeff: T,
+    release_slow_coeff_lin: T,
+    held_samples: usize,
 }
 
-impl CompressorSolver {
-    pub fn new(sample_rate: f32) -> Self {
+impl<T: Sample> CompressorSolver<T> {
+    pub fn new(sample_rate: T) -> Self {
         Self {
             sample_rate,
             ..Default::default()
         }
     }
-    pub fn update_ratio(&mut self, ratio: f32) {
-        self.ratio = 1.0 - (1.0 / ratio)
+    /// coefficients are recomputed by the next `update_*()` calls
+    pub fn set_sample_rate(&mut self, sample_rate: T) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn update_ratio(&mut self, ratio: T) {
+        self.ratio = T::one() - (T::one() / ratio)
     }
 
-    pub fn update_knee_width(&mut self, knee_width_db: f32) {
+    pub fn update_knee_width(&mut self, knee_width_db: T) {
         self.knee_width = knee_width_db;
-        self.knee_width_x0_5 = knee_width_db / 2.0;
-        self.knee_width_x2 = knee_width_db * 2.0;
+        self.knee_width_x0_5 = knee_width_db / T::lit(2.0);
+        self.knee_width_x2 = knee_width_db * T::lit(2.0);
     }
 
-    pub fn update_attack(&mut self, attack_msec: f32) {
-        let attack_msec = attack_msec.max(0.0);
+    pub fn update_attack(&mut self, attack_msec: T) {
+        let attack_msec = attack_msec.max(T::zero());
         self.attack_msec = attack_msec;
 
-        if attack_msec == 0.0 {
-            self.attack_coeff = 0.0;
+        if attack_msec == T::zero() {
+            self.attack_coeff = T::zero();
         } else {
-            self.attack_coeff = (0.10_f32.ln() / (attack_msec * self.sample_rate / 1000.0)).exp();
+            self.attack_coeff = time_coeff(attack_msec, self.sample_rate);
         }
     }
-    pub fn update_release(&mut self, release_msec: f32) {
-        let release_msec = release_msec.max(0.0);
+    pub fn update_release(&mut self, release_msec: T) {
+        let release_msec = release_msec.max(T::zero());
         self.release_msec = release_msec;
 
-        if release_msec == 0.0 {
-            self.release_coeff_lin = 0.0;
-            self.release_coeff = 0.0;
+        if release_msec == T::zero() {
+            self.release_coeff_lin = T::zero();
+            self.release_coeff = T::zero();
         } else {
-            self.release_coeff_lin = 10.0 / (release_msec * self.sample_rate / 1000.0);
-            self.release_coeff = (0.10_f32.ln() / (release_msec * self.sample_rate / 1000.0)).exp();
+            self.release_coeff_lin = lin_coeff(release_msec, self.sample_rate);
+            self.release_coeff = time_coeff(release_msec, self.sample_rate);
+        }
+
+        let fast_msec = T::lit(AUTO_RELEASE_FAST_MSEC);
+        let slow_msec = T::lit(AUTO_RELEASE_SLOW_MSEC);
+        self.release_fast_coeff_lin = lin_coeff(fast_msec, self.sample_rate);
+        self.release_fast_coeff = time_coeff(fast_msec, self.sample_rate);
+        self.release_slow_coeff_lin = lin_coeff(slow_msec, self.sample_rate);
+        self.release_slow_coeff = time_coeff(slow_msec, self.sample_rate);
+    }
+
+    /// clears the state, the settings are kept
+    pub fn reset(&mut self) {
+        self.held_samples = 0;
+    }
+
+    /// program dependent release, replaces the fixed release time while enabled
+    pub fn update_auto_release(&mut self, auto_release: bool) {
+        self.auto_release = auto_release;
+    }
+
+    /// keeps track of how long reduction has been held for the auto release.
+    ///
+    /// The counter runs while the detector asks for reduction, stays put while releasing and
+    /// starts over once the reduction is gone.
+    pub fn track_reduction(&mut self, curr_reduction: T, new_reduction: T) {
+        if !self.auto_release {
+            return;
+        }
+
+        let active_db = T::lit(AUTO_RELEASE_ACTIVE_DB);
+        if new_reduction > active_db {
+            self.held_samples = self.held_samples.saturating_add(1);
+        } else if curr_reduction < active_db {
+            self.held_samples = 0;
+        }
+    }
+
+    // (log, lin) release coefficients, blended from fast to slow the longer reduction was held
+    fn release_coeffs(&self) -> (T, T) {
+        if !self.auto_release {
+            return (self.release_coeff, self.release_coeff_lin);
+        }
+
+        let held_msec = T::lit(self.held_samples as f64 * 1000.0) / self.sample_rate;
+        let slow_weight = held_msec / (held_msec + T::lit(AUTO_RELEASE_BLEND_MSEC));
+
+        (
+            run_alpha_beta(
+                slow_weight,
+                self.release_slow_coeff,
+                self.release_fast_coeff,
+            ),
+            run_alpha_beta(
+                slow_weight,
+                self.release_slow_coeff_lin,
+                self.release_fast_coeff_lin,
+            ),
+        )
+    }
+
+    pub fn update_lower_mode(&mut self, mode: LowerMode) {
+        self.lower_mode = mode;
+    }
+
+    pub fn update_lower_ratio(&mut self, ratio: T) {
+        self.lower_ratio = ratio.max(T::one());
+    }
+
+    pub fn update_max_gain(&mut self, max_gain_db: T) {
+        self.max_gain_db = max_gain_db.max(T::zero());
+    }
+
+    pub fn update_range(&mut self, range_db: T) {
+        self.range_db = range_db.max(T::zero());
+    }
+
+    pub fn update_hold(&mut self, hold_msec: T) {
+        self.hold_samples = (hold_msec.max(T::zero()) * self.sample_rate / T::lit(1000.0))
+            .to_usize()
+            .unwrap_or(0);
+    }
+
+    pub fn hold_samples(&self) -> usize {
+        self.hold_samples
+    }
+
+    pub fn hold_msec(&self) -> T {
+        T::lit(self.hold_samples as f64 * 1000.0) / self.sample_rate
+    }
+
+    // the slowest release time the reduction can fall back with
+    fn release_time_msec(&self) -> T {
+        if self.auto_release {
+            T::lit(AUTO_RELEASE_SLOW_MSEC)
+        } else {
+            self.release_msec
+        }
+    }
+
+    /// how long `reduction` (in dB) keeps releasing once the detector went quiet
+    pub fn release_tail_msec(&self, reduction: T, curve_type: &CurveType) -> T {
+        let settled = T::lit(RELEASE_SETTLED_DB);
+        if reduction <= settled {
+            return T::zero();
+        }
+
+        let release_times = match curve_type {
+            // a straight line, 10 dB per release time
+            CurveType::LogLin => reduction / T::lit(10.0),
+            // 10% of the reduction is left after every release time
+            CurveType::LogSmoothDecoupled | CurveType::LogSmoothBranching => {
+                (reduction / settled).log10()
+            }
+        };
+
+        release_times * self.release_time_msec()
+    }
+
+    pub fn lower_mode(&self) -> LowerMode {
+        self.lower_mode
+    }
+
+    /// reduction (in dB) contributed by the lower threshold. negative values are upward gain.
+    pub fn get_lower_reduction(&self, input_level: T) -> T {
+        let diff_threshold = self.lower_threshold - input_level;
+
+        if diff_threshold <= T::zero() {
+            return T::zero();
+        }
+
+        match self.lower_mode {
+            LowerMode::Off => T::zero(),
+            LowerMode::Upward => {
+                -(diff_threshold * (T::one() - (T::one() / self.lower_ratio))).min(self.max_gain_db)
+            }
+            LowerMode::Expand => {
+                (diff_threshold * (self.lower_ratio - T::one())).min(self.range_db)
+            }
         }
     }
 
-    pub fn get_ideal_reduction(&self, input_level: f32) -> f32 {
+    pub fn get_ideal_reduction(&self, input_level: T) -> T {
         let diff_threshold = input_level - self.threshold;
 
-        if self.knee_width == 0.0 {
-            if diff_threshold <= 0.0 {
-                return 0.0;
+        if self.knee_width == T::zero() {
+            if diff_threshold <= T::zero() {
+                return T::zero();
             } else {
                 return diff_threshold * self.ratio;
             }
         }
 
-        match diff_threshold.total_cmp(&self.knee_width_x0_5) {
-            Ordering::Less => 0.0,
+        // only NaN is unordered, it stays NaN either way
+        match diff_threshold
+            .partial_cmp(&self.knee_width_x0_5)
+            .unwrap_or(Ordering::Equal)
+        {
+            Ordering::Less => T::zero(),
             Ordering::Greater => diff_threshold * self.ratio,
             Ordering::Equal => {
                 let factor = diff_threshold + self.knee_width_x0_5;
@@ -91,12 +287,12 @@ impl CompressorSolver {
         }
     }
 
-    fn curve_lin(&self, curr_reduction: f32, new_reduction: f32) -> (f32, f32) {
+    fn curve_lin(&self, curr_reduction: T, new_reduction: T) -> (T, T) {
         if new_reduction >= curr_reduction {
             let res = run_alpha_beta(self.attack_coeff, curr_reduction, new_reduction);
             return (res, res);
         } else {
-            let reduction = curr_reduction - self.release_coeff_lin;
+            let reduction = curr_reduction - self.release_coeffs().1;
 
             if reduction < new_reduction {
                 (new_reduction, reduction)
@@ -106,25 +302,25 @@ impl CompressorSolver {
         }
     }
 
-    fn curve_smoothdecoupled(&self, _curr_reduction: f32, _new_reduction: f32) -> (f32, f32) {
+    fn curve_smoothdecoupled(&self, _curr_reduction: T, _new_reduction: T) -> (T, T) {
         todo!()
     }
-    fn curve_smoothbranching(&self, curr_reduction: f32, new_reduction: f32) -> (f32, f32) {
+    fn curve_smoothbranching(&self, curr_reduction: T, new_reduction: T) -> (T, T) {
         if new_reduction > curr_reduction {
             let res = run_alpha_beta(self.attack_coeff, curr_reduction, new_reduction);
             return (res, res);
         } else {
-            let res = run_alpha_beta(self.release_coeff, curr_reduction, new_reduction);
+            let res = run_alpha_beta(self.release_coeffs().0, curr_reduction, new_reduction);
             return (res, res);
         }
     }
 
     pub fn apply_curve(
         &self,
-        curr_reduction: f32,
-        new_reduction: f32,
+        curr_reduction: T,
+        new_reduction: T,
         curve_type: &CurveType,
-    ) -> (f32, f32) {
+    ) -> (T, T) {
         match curve_type {
             CurveType::LogLin => self.curve_lin(curr_reduction, new_reduction),
             CurveType::LogSmoothDecoupled => {
@@ -0,0 +1,52 @@
+use std::fmt::Debug;
+
+use nih_plug::util;
+use num_traits::{Float, FromPrimitive};
+
+// -100 dB, same floor as nih_plug's conversions
+const MINUS_INFINITY_GAIN: f64 = 1e-5;
+// far below anything audible or any meaningful reduction, but well above the subnormals
+const DENORMAL_FLOOR: f64 = 1e-30;
+
+/// Sample type of the compressor core, `f32` in the plugin and `f64` for offline rendering.
+pub trait Sample: Float + FromPrimitive + Default + Debug + Send + Sync + 'static {
+    /// for constants, rounds to the nearest value for `f32`
+    fn lit(value: f64) -> Self {
+        Self::from_f64(value).unwrap()
+    }
+
+    /// zero for anything too small to matter, keeps decaying followers out of the subnormals
+    #[inline]
+    fn flush_denormal(self) -> Self {
+        if self.abs() < Self::lit(DENORMAL_FLOOR) {
+            Self::zero()
+        } else {
+            self
+        }
+    }
+
+    fn gain_to_db(self) -> Self;
+
+    fn db_to_gain(self) -> Self;
+}
+
+// the plugin keeps using nih_plug's fast approximations
+impl Sample for f32 {
+    fn gain_to_db(self) -> Self {
+        util::gain_to_db_fast(self)
+    }
+
+    fn db_to_gain(self) -> Self {
+        util::db_to_gain_fast(self)
+    }
+}
+
+impl Sample for f64 {
+    fn gain_to_db(self) -> Self {
+        20.0 * self.max(MINUS_INFINITY_GAIN).log10()
+    }
+
+    fn db_to_gain(self) -> Self {
+        10.0_f64.powf(self / 20.0)
+    }
+}
@@ -0,0 +1,157 @@
+use cute_dsp::filters::Biquad;
+use nih_plug::prelude::Enum;
+
+const DEESS_SHELF_GAIN_DB: f32 = 12.0;
+const DEESS_Q: f32 = 1.0;
+const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+const TILT_Q: f32 = 0.5;
+
+/// Detector curve used to pick up sibilance.
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeEssShape {
+    #[default]
+    Bandpass,
+    #[name = "High Shelf"]
+    HighShelf,
+}
+
+/// Sidechain for the de-esser band.
+///
+/// `detector` feeds the compressor, `split` isolates the sibilant band so that split mode can
+/// reduce only that part of the signal. The split filter is a constant peak gain bandpass, so
+/// `smp - split` is the matching notch and the two sum back to the input.
+pub struct DeEsser {
+    detector: Biquad<f32>,
+    split: Biquad<f32>,
+}
+
+impl Default for DeEsser {
+    fn default() -> Self {
+        Self {
+            detector: Biquad::<f32>::new(true),
+            split: Biquad::<f32>::new(true),
+        }
+    }
+}
+
+impl DeEsser {
+    /// `freq` is normalised to the sample rate.
+    pub fn update(&mut self, freq: f32, shape: DeEssShape) {
+        match shape {
+            DeEssShape::Bandpass => self.detector.bandpass(freq, DEESS_Q),
+            DeEssShape::HighShelf => {
+                self.detector
+                    .high_shelf_db(freq, DEESS_SHELF_GAIN_DB, DEESS_Q)
+            }
+        };
+        self.split.bandpass(freq, DEESS_Q);
+    }
+
+    pub fn reset(&mut self) {
+        self.detector.reset();
+        self.split.reset();
+    }
+
+    /// returns (detector signal, sibilant band). The detector listens to `key`, which is the
+    /// band itself unless the band is keyed wideband or linked.
+    pub fn process(&mut self, smp: f32, key: f32) -> (f32, f32) {
+        (self.detector.process(key), self.split.process(smp))
+    }
+}
+
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SidechainEqMode {
+    #[default]
+    Off,
+    Peak,
+    /// opposite shelves around the pivot, positive gain favours the highs
+    Tilt,
+}
+
+/// Detector path filtering for a band, never touches the audio path.
+///
+/// All filter states live in the struct so nothing is allocated while processing.
+pub struct SidechainFilter {
+    hpf_enabled: bool,
+    lpf_enabled: bool,
+    eq_mode: SidechainEqMode,
+    hpf: Biquad<f32>,
+    lpf: Biquad<f32>,
+    eq_low: Biquad<f32>,
+    eq_high: Biquad<f32>,
+}
+
+impl Default for SidechainFilter {
+    fn default() -> Self {
+        Self {
+            hpf_enabled: false,
+            lpf_enabled: false,
+            eq_mode: SidechainEqMode::default(),
+            hpf: Biquad::<f32>::new(true),
+            lpf: Biquad::<f32>::new(true),
+            eq_low: Biquad::<f32>::new(true),
+            eq_high: Biquad::<f32>::new(true),
+        }
+    }
+}
+
+impl SidechainFilter {
+    /// `hpf_freq`, `lpf_freq` and `eq_freq` are normalised to the sample rate, `None` disables
+    /// the filter.
+    pub fn update(
+        &mut self,
+        hpf_freq: Option<f32>,
+        lpf_freq: Option<f32>,
+        eq_mode: SidechainEqMode,
+        eq_freq: f32,
+        eq_gain_db: f32,
+        eq_q: f32,
+    ) {
+        self.hpf_enabled = hpf_freq.is_some();
+        if let Some(freq) = hpf_freq {
+            self.hpf.highpass(freq, BUTTERWORTH_Q);
+        }
+
+        self.lpf_enabled = lpf_freq.is_some();
+        if let Some(freq) = lpf_freq {
+            self.lpf.lowpass(freq, BUTTERWORTH_Q);
+        }
+
+        self.eq_mode = eq_mode;
+        match eq_mode {
+            SidechainEqMode::Off => {}
+            SidechainEqMode::Peak => {
+                self.eq_low.peak_db(eq_freq, eq_gain_db, eq_q);
+            }
+            SidechainEqMode::Tilt => {
+                self.eq_low.low_shelf_db(eq_freq, -eq_gain_db / 2.0, TILT_Q);
+                self.eq_high
+                    .high_shelf_db(eq_freq, eq_gain_db / 2.0, TILT_Q);
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.hpf.reset();
+        self.lpf.reset();
+        self.eq_low.reset();
+        self.eq_high.reset();
+    }
+
+    pub fn process(&mut self, smp: f32) -> f32 {
+        let mut smp = smp;
+
+        if self.hpf_enabled {
+            smp = self.hpf.process(smp);
+        }
+        if self.lpf_enabled {
+            smp = self.lpf.process(smp);
+        }
+
+        match self.eq_mode {
+            SidechainEqMode::Off => smp,
+            SidechainEqMode::Peak => self.eq_low.process(smp),
+            SidechainEqMode::Tilt => self.eq_high.process(self.eq_low.process(smp)),
+        }
+    }
+}
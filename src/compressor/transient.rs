@@ -0,0 +1,99 @@
+use nih_plug::util;
+
+use super::process::run_alpha_beta;
+
+const FAST_ATTACK_MSEC: f32 = 0.5;
+const SLOW_ATTACK_MSEC: f32 = 20.0;
+const FAST_RELEASE_MSEC: f32 = 30.0;
+const SLOW_RELEASE_MSEC: f32 = 400.0;
+// envelope difference that counts as a full transient/sustain
+const DETECT_RANGE_DB: f32 = 12.0;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Follower {
+    attack_coeff: f32,
+    release_coeff: f32,
+    env: f32,
+}
+
+impl Follower {
+    fn update(&mut self, sample_rate: f32, attack_msec: f32, release_msec: f32) {
+        self.attack_coeff = (0.10_f32.ln() / (attack_msec * sample_rate / 1000.0)).exp();
+        self.release_coeff = (0.10_f32.ln() / (release_msec * sample_rate / 1000.0)).exp();
+    }
+
+    fn process(&mut self, level: f32) -> f32 {
+        let coeff = if level > self.env {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.env = run_alpha_beta(coeff, self.env, level);
+        self.env
+    }
+}
+
+/// Level independent transient/sustain shaping instead of compression.
+///
+/// Two follower pairs share one time constant and differ in the other: a fast and a slow
+/// attack pick up the transients, a fast and a slow release pick up the sustain. How far each
+/// pair drifts apart scales the user's attack and sustain gains.
+#[derive(Debug, Default)]
+pub struct TransientShaper {
+    attack_gain_db: f32,
+    sustain_gain_db: f32,
+    attack_fast: Follower,
+    attack_slow: Follower,
+    sustain_fast: Follower,
+    sustain_slow: Follower,
+}
+
+impl TransientShaper {
+    pub fn update(&mut self, sample_rate: f32, attack_gain_db: f32, sustain_gain_db: f32) {
+        self.attack_gain_db = attack_gain_db;
+        self.sustain_gain_db = sustain_gain_db;
+
+        self.attack_fast
+            .update(sample_rate, FAST_ATTACK_MSEC, FAST_RELEASE_MSEC);
+        self.attack_slow
+            .update(sample_rate, SLOW_ATTACK_MSEC, FAST_RELEASE_MSEC);
+        self.sustain_fast
+            .update(sample_rate, FAST_ATTACK_MSEC, FAST_RELEASE_MSEC);
+        self.sustain_slow
+            .update(sample_rate, FAST_ATTACK_MSEC, SLOW_RELEASE_MSEC);
+    }
+
+    /// the slow sustain follower is the last to settle
+    pub fn release_tail_msec(&self) -> f32 {
+        SLOW_RELEASE_MSEC
+    }
+
+    pub fn reset(&mut self) {
+        self.attack_fast.env = 0.0;
+        self.attack_slow.env = 0.0;
+        self.sustain_fast.env = 0.0;
+        self.sustain_slow.env = 0.0;
+    }
+
+    fn amount(fast: f32, slow: f32) -> f32 {
+        (util::gain_to_db_fast(fast) - util::gain_to_db_fast(slow)).clamp(0.0, DETECT_RANGE_DB)
+            / DETECT_RANGE_DB
+    }
+
+    pub fn process(&mut self, smp: f32, sidechain: f32) -> f32 {
+        let level = sidechain.abs();
+
+        let transient = Self::amount(
+            self.attack_fast.process(level),
+            self.attack_slow.process(level),
+        );
+        let sustain = Self::amount(
+            self.sustain_slow.process(level),
+            self.sustain_fast.process(level),
+        );
+
+        smp * util::db_to_gain_fast(
+            self.attack_gain_db * transient + self.sustain_gain_db * sustain,
+        )
+    }
+}
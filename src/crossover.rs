@@ -0,0 +1,183 @@
+use cute_dsp::filters::Biquad;
+
+use crate::MAX_MBCS;
+
+const MAX_CROSSOVERS: usize = MAX_MBCS - 1;
+const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+// neighbouring crossovers are kept at least about a third of an octave apart
+const MIN_SPACING: f32 = 1.25;
+
+/// Puts crossover frequencies in ascending order, handles that were dragged past each other
+/// are pushed apart. Higher crossovers give way to lower ones unless that would take them over
+/// `max_freq`.
+pub fn order_freqs(freqs: &mut [f32], max_freq: f32) {
+    for idx in 1..freqs.len() {
+        freqs[idx] = freqs[idx].max(freqs[idx - 1] * MIN_SPACING);
+    }
+
+    let mut upper = max_freq * MIN_SPACING;
+    for freq in freqs.iter_mut().rev() {
+        *freq = freq.min(upper / MIN_SPACING);
+        upper = *freq;
+    }
+}
+
+/// Linkwitz-Riley (LR4) band splitter.
+///
+/// The signal is split low to high: every crossover peels the lowest band off the remainder.
+/// An LR4 low/high pair sums to a second order allpass, so each band that was split off early
+/// runs through the allpasses of the crossovers after it. That keeps all bands phase aligned
+/// and makes their plain sum magnitude flat.
+pub struct Crossover {
+    band_count: usize,
+    lows: [[Biquad<f32>; 2]; MAX_CROSSOVERS],
+    highs: [[Biquad<f32>; 2]; MAX_CROSSOVERS],
+    // allpasses[band][crossover]
+    allpasses: [[Biquad<f32>; MAX_CROSSOVERS]; MAX_MBCS],
+}
+
+impl Default for Crossover {
+    fn default() -> Self {
+        Self {
+            band_count: MAX_MBCS,
+            lows: std::array::from_fn(|_| std::array::from_fn(|_| Biquad::<f32>::new(true))),
+            highs: std::array::from_fn(|_| std::array::from_fn(|_| Biquad::<f32>::new(true))),
+            allpasses: std::array::from_fn(|_| std::array::from_fn(|_| Biquad::<f32>::new(true))),
+        }
+    }
+}
+
+impl Crossover {
+    /// `freqs` are normalised to the sample rate, only the first `band_count - 1` are used.
+    pub fn update(&mut self, freqs: &[f32; MAX_CROSSOVERS], band_count: usize) {
+        self.band_count = band_count.clamp(1, MAX_MBCS);
+
+        for (idx, freq) in freqs.iter().enumerate().take(self.band_count - 1) {
+            for filt in self.lows[idx].iter_mut() {
+                filt.lowpass(*freq, BUTTERWORTH_Q);
+            }
+            for filt in self.highs[idx].iter_mut() {
+                filt.highpass(*freq, BUTTERWORTH_Q);
+            }
+            for band in self.allpasses.iter_mut() {
+                band[idx].allpass(*freq, BUTTERWORTH_Q);
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.lows
+            .iter_mut()
+            .chain(self.highs.iter_mut())
+            .flatten()
+            .chain(self.allpasses.iter_mut().flatten())
+            .for_each(|filt| filt.reset());
+    }
+
+    pub fn band_count(&self) -> usize {
+        self.band_count
+    }
+
+    /// returns the bands low to high, bands past `band_count` are silent
+    pub fn split(&mut self, smp: f32) -> [f32; MAX_MBCS] {
+        let mut bands = [0.0; MAX_MBCS];
+        let mut rest = smp;
+        let last = self.band_count - 1;
+
+        for idx in 0..last {
+            let low = self.lows[idx]
+                .iter_mut()
+                .fold(rest, |acc, filt| filt.process(acc));
+            rest = self.highs[idx]
+                .iter_mut()
+                .fold(rest, |acc, filt| filt.process(acc));
+
+            bands[idx] = self.allpasses[idx][(idx + 1)..last]
+                .iter_mut()
+                .fold(low, |acc, filt| filt.process(acc));
+        }
+        bands[last] = rest;
+
+        bands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const FREQS: [f32; MAX_CROSSOVERS] = [120.0, 500.0, 2_000.0, 6_000.0];
+
+    // rms of every band and of their sum relative to a sine at `freq`, in dB
+    fn gains_db(crossover: &mut Crossover, freq: f32) -> ([f32; MAX_MBCS], f32) {
+        crossover.reset();
+
+        // the second half is a whole number of periods at all the test frequencies
+        let len = SAMPLE_RATE as usize / 2;
+        let mut input_sq = 0.0_f64;
+        let mut band_sq = [0.0_f64; MAX_MBCS];
+        let mut sum_sq = 0.0_f64;
+        for idx in 0..len * 2 {
+            let phase = (idx as f64 * freq as f64 / SAMPLE_RATE as f64).fract();
+            let smp = (std::f64::consts::TAU * phase).sin() as f32;
+            let bands = crossover.split(smp);
+            if idx >= len {
+                input_sq += (smp * smp) as f64;
+                for (sq, band) in band_sq.iter_mut().zip(bands) {
+                    *sq += (band * band) as f64;
+                }
+                let sum: f32 = bands.iter().sum();
+                sum_sq += (sum * sum) as f64;
+            }
+        }
+
+        let to_db = |sq: f64| (10.0 * (sq / input_sq).log10()) as f32;
+        (band_sq.map(to_db), to_db(sum_sq))
+    }
+
+    #[test]
+    fn bands_sum_flat() {
+        for band_count in 1..=MAX_MBCS {
+            let mut crossover = Crossover::default();
+            crossover.update(&FREQS.map(|freq| freq / SAMPLE_RATE), band_count);
+            assert_eq!(crossover.band_count(), band_count);
+
+            for freq in [50.0, 120.0, 300.0, 1000.0, 2000.0, 5000.0, 12000.0] {
+                let (bands, sum) = gains_db(&mut crossover, freq);
+                assert!(sum.abs() < 0.05, "{band_count} bands, {freq} Hz: {sum} dB");
+                assert!(
+                    bands[band_count..].iter().all(|band| *band == f32::NEG_INFINITY),
+                    "{band_count} bands, {freq} Hz: {bands:?}"
+                );
+            }
+        }
+
+        // every crossover leaves its two bands 6 dB down
+        let mut crossover = Crossover::default();
+        crossover.update(&FREQS.map(|freq| freq / SAMPLE_RATE), MAX_MBCS);
+        for (idx, freq) in FREQS.iter().enumerate() {
+            let (bands, _) = gains_db(&mut crossover, *freq);
+            for band in &bands[idx..=idx + 1] {
+                assert!((band + 6.02).abs() < 0.2, "{freq} Hz: {bands:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn freqs_stay_ordered() {
+        let mut freqs = [2_000.0, 500.0, 500.0, 6_000.0];
+        order_freqs(&mut freqs, 20_000.0);
+        assert_eq!(freqs, [2_000.0, 2_500.0, 3_125.0, 6_000.0]);
+
+        // nothing goes past the top, the lower crossovers make room instead
+        let mut freqs = [1_000.0, 19_000.0, 19_000.0, 30_000.0];
+        order_freqs(&mut freqs, 20_000.0);
+        assert_eq!(freqs, [1_000.0, 12_800.0, 16_000.0, 20_000.0]);
+
+        // only the crossovers in use are touched
+        let mut freqs = [2_000.0, 500.0, 100.0, 50.0];
+        order_freqs(&mut freqs[..2], 20_000.0);
+        assert_eq!(freqs, [2_000.0, 2_500.0, 100.0, 50.0]);
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+use nih_plug::util;
+
+// note-ons waiting out their delay, the delay is the oversampling latency so this covers one
+// note-on per sample
+const MAX_PENDING: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Attack,
+    Hold,
+    Release,
+}
+
+/// Note triggered gain reduction envelope for sidechain style pumping.
+///
+/// The envelope level runs from 0 (no ducking) to 1 (full depth). `curve` bends the attack and
+/// release segments, 1 is linear, higher values start slow and end fast.
+pub struct Ducker {
+    depth_db: f32,
+    curve: f32,
+    attack_samples: usize,
+    hold_samples: usize,
+    release_samples: usize,
+    stage: Stage,
+    pos: usize,
+    level: f32,
+    start_level: f32,
+    clock: usize,
+    // samples the pending note-ons are due at, in order
+    pending: VecDeque<usize>,
+}
+
+impl Default for Ducker {
+    fn default() -> Self {
+        Self {
+            depth_db: 0.0,
+            curve: 1.0,
+            attack_samples: 0,
+            hold_samples: 0,
+            release_samples: 0,
+            stage: Stage::Idle,
+            pos: 0,
+            level: 0.0,
+            start_level: 0.0,
+            clock: 0,
+            pending: VecDeque::with_capacity(MAX_PENDING),
+        }
+    }
+}
+
+impl Ducker {
+    pub fn update(
+        &mut self,
+        sample_rate: f32,
+        depth_db: f32,
+        attack_msec: f32,
+        hold_msec: f32,
+        release_msec: f32,
+        curve: f32,
+    ) {
+        self.depth_db = depth_db;
+        self.curve = curve;
+        self.attack_samples = (attack_msec * sample_rate / 1000.0) as usize;
+        self.hold_samples = (hold_msec * sample_rate / 1000.0) as usize;
+        self.release_samples = (release_msec * sample_rate / 1000.0) as usize;
+    }
+
+    /// length of a full envelope, nothing when it doesn't duck at all
+    pub fn tail_samples(&self) -> usize {
+        if self.depth_db > 0.0 {
+            self.attack_samples + self.hold_samples + self.release_samples
+        } else {
+            0
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.stage = Stage::Idle;
+        self.pos = 0;
+        self.level = 0.0;
+        self.start_level = 0.0;
+        self.pending.clear();
+    }
+
+    /// starts the envelope `delay` samples from now, so it lines up with latency on the audio.
+    /// earlier note-ons that are still waiting keep their own start
+    pub fn trigger(&mut self, delay: usize) {
+        let due = self.clock.wrapping_add(delay);
+        // note-ons on the same sample start the envelope once, a full queue keeps the earliest
+        if self.pending.back() == Some(&due) || self.pending.len() == MAX_PENDING {
+            return;
+        }
+
+        self.pending.push_back(due);
+    }
+
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        self.pos = 0;
+        self.start_level = self.level;
+    }
+
+    fn shape(&self, pos: usize, len: usize) -> f32 {
+        (pos as f32 / len as f32).powf(self.curve)
+    }
+
+    /// returns the linear gain for the next sample
+    pub fn next(&mut self) -> f32 {
+        if self.pending.front() == Some(&self.clock) {
+            self.pending.pop_front();
+            self.enter(Stage::Attack);
+        }
+        self.clock = self.clock.wrapping_add(1);
+
+        if self.stage == Stage::Attack && self.pos >= self.attack_samples {
+            self.level = 1.0;
+            self.enter(Stage::Hold);
+        }
+        if self.stage == Stage::Hold && self.pos >= self.hold_samples {
+            self.enter(Stage::Release);
+        }
+        if self.stage == Stage::Release && self.pos >= self.release_samples {
+            self.level = 0.0;
+            self.enter(Stage::Idle);
+        }
+
+        match self.stage {
+            Stage::Idle => return 1.0,
+            Stage::Attack => {
+                let shape = self.shape(self.pos, self.attack_samples);
+                self.level = self.start_level + (1.0 - self.start_level) * shape;
+            }
+            Stage::Hold => {}
+            Stage::Release => {
+                let shape = self.shape(self.pos, self.release_samples);
+                self.level = self.start_level * (1.0 - shape);
+            }
+        }
+        self.pos += 1;
+
+        util::db_to_gain_fast(-self.depth_db * self.level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_ons_in_one_block() {
+        let mut ducker = Ducker::default();
+        // 1 ms attack, no hold, 10 ms release
+        ducker.update(48000.0, 12.0, 1.0, 0.0, 10.0, 1.0);
+
+        // both note-ons come in while the first one still waits out the latency
+        let gains: Vec<f32> = (0..1000)
+            .map(|idx| {
+                if idx == 0 || idx == 5 {
+                    ducker.trigger(10);
+                }
+                ducker.next()
+            })
+            .collect();
+
+        // the first one starts on time, the second restarts the attack from where it got to
+        assert!(gains[..=10].iter().all(|gain| *gain == 1.0));
+        assert!(gains[11] < 1.0);
+        assert!(gains[10..=63].windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!((util::gain_to_db(gains[63]) + 12.0).abs() < 0.1, "{}", gains[63]);
+        assert!(gains[64] > gains[63]);
+        assert_eq!(gains[999], 1.0);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+use atomic_float::AtomicF32;
+use nih_plug::prelude::{Editor, GuiContext, ParamPtr, ParamSetter, Params};
+use nih_plug_vizia::vizia::prelude::*;
+use nih_plug_vizia::widgets::{GenericUi, RawParamEvent};
+use nih_plug_vizia::{assets, create_vizia_editor, ViziaState, ViziaTheming};
+
+use crate::history::{self, History};
+use crate::learn::{self, BandStats, Learner};
+use crate::loudness::LoudnessReadings;
+use crate::{OpenMbcParams, MAX_MBCS};
+
+const EDITOR_WIDTH: u32 = 900;
+const EDITOR_HEIGHT: u32 = 560;
+
+#[derive(Lens)]
+struct Data {
+    params: Arc<OpenMbcParams>,
+    gui_context: Arc<dyn GuiContext>,
+    history: Arc<Mutex<History>>,
+    learner: Arc<Mutex<Learner>>,
+    input_loudness: Arc<LoudnessReadings>,
+    output_loudness: Arc<LoudnessReadings>,
+    // the widgets only know the pointers, the history goes by ID
+    param_ids: HashMap<ParamPtr, String>,
+    // latest value of every running gesture
+    gestures: HashMap<ParamPtr, f32>,
+}
+
+enum EditorEvent {
+    Undo,
+    Redo,
+    SwitchAb,
+    CopyAb,
+    SwapAb,
+    ApplyLearned,
+}
+
+impl Data {
+    // the widgets' edits become undo steps, the values set through `history` don't pass here
+    fn record(&mut self, event: &RawParamEvent) {
+        let mut history = self.history.lock().unwrap();
+        match *event {
+            RawParamEvent::BeginSetParameter(ptr) => {
+                if let Some(id) = self.param_ids.get(&ptr) {
+                    history.begin(id, unsafe { ptr.unmodulated_normalized_value() });
+                }
+            }
+            RawParamEvent::SetParameterNormalized(ptr, value) => {
+                self.gestures.insert(ptr, value);
+            }
+            RawParamEvent::EndSetParameter(ptr) => {
+                let value = self
+                    .gestures
+                    .remove(&ptr)
+                    .unwrap_or_else(|| unsafe { ptr.unmodulated_normalized_value() });
+                if let Some(id) = self.param_ids.get(&ptr) {
+                    history.end(id, value);
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, event: &EditorEvent) {
+        let setter = ParamSetter::new(self.gui_context.as_ref());
+        let params = self.params.as_ref();
+        let mut history = self.history.lock().unwrap();
+
+        match event {
+            EditorEvent::Undo => {
+                if let Some(values) = history.undo() {
+                    history::apply(params, &setter, &values);
+                }
+            }
+            EditorEvent::Redo => {
+                if let Some(values) = history.redo() {
+                    history::apply(params, &setter, &values);
+                }
+            }
+            EditorEvent::SwitchAb | EditorEvent::CopyAb | EditorEvent::SwapAb => {
+                let live = history::snapshot(params);
+                let values = {
+                    let mut ab_slots = params.ab_slots.write().unwrap();
+                    match event {
+                        EditorEvent::SwitchAb => ab_slots.switch(live),
+                        EditorEvent::CopyAb => {
+                            let active = ab_slots.active;
+                            ab_slots.copy(active, live)
+                        }
+                        _ => ab_slots.swap(live),
+                    }
+                };
+                if let Some(values) = values {
+                    history::load(params, &setter, &mut history, &values);
+                }
+            }
+            EditorEvent::ApplyLearned => {
+                let proposal = self.learner.lock().unwrap().take_proposal();
+                if let Some(thresholds) = proposal {
+                    learn::apply_proposal(params, &setter, &mut history, &thresholds);
+                }
+            }
+        }
+    }
+}
+
+impl Model for Data {
+    fn event(&mut self, _cx: &mut EventContext, event: &mut Event) {
+        event.map(|param_event: &RawParamEvent, _| self.record(param_event));
+        event.map(|editor_event: &EditorEvent, _| self.handle(editor_event));
+    }
+}
+
+// "-" until the meter has enough signal for a reading
+fn loudness_text(name: &str, readings: &LoudnessReadings) -> String {
+    let lufs = |reading: &AtomicF32| {
+        let value = reading.load(Ordering::Relaxed);
+        if value.is_finite() {
+            format!("{value:.1}")
+        } else {
+            "-".to_string()
+        }
+    };
+
+    format!(
+        "{name}  M {}  S {}  I {} LUFS  LRA {:.1} LU",
+        lufs(&readings.momentary),
+        lufs(&readings.short_term),
+        lufs(&readings.integrated),
+        readings.range.load(Ordering::Relaxed)
+    )
+}
+
+// peak, rms and crest factor at the learned percentiles
+fn stats_text(band: usize, stats: Option<BandStats>) -> String {
+    let Some(stats) = stats else {
+        return format!("Band {}  -", band + 1);
+    };
+    let percentiles = |values: &[f32]| {
+        values
+            .iter()
+            .map(|value| format!("{value:.1}"))
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+
+    format!(
+        "Band {}  peak {}  rms {}  crest {} dB",
+        band + 1,
+        percentiles(&stats.peak),
+        percentiles(&stats.rms),
+        percentiles(&stats.crest)
+    )
+}
+
+fn button(cx: &mut Context, label: &'static str, event: fn() -> EditorEvent) -> Handle<Button> {
+    Button::new(cx, move |cx| cx.emit(event()), move |cx| Label::new(cx, label))
+}
+
+/// Size the editor opens at until the host restores a saved one.
+pub fn default_state() -> Arc<ViziaState> {
+    ViziaState::new(|| (EDITOR_WIDTH, EDITOR_HEIGHT))
+}
+
+/// Generic parameter list with undo/redo, A/B compare, the input and output loudness and the
+/// learned levels on top, opened at the persisted editor size.
+pub fn create(
+    params: Arc<OpenMbcParams>,
+    history: Arc<Mutex<History>>,
+    learner: Arc<Mutex<Learner>>,
+    input_loudness: Arc<LoudnessReadings>,
+    output_loudness: Arc<LoudnessReadings>,
+) -> Option<Box<dyn Editor>> {
+    let editor_state = params.editor_state.clone();
+
+    create_vizia_editor(editor_state, ViziaTheming::Custom, move |cx, gui_context| {
+        assets::register_noto_sans_light(cx);
+
+        Data {
+            params: params.clone(),
+            gui_context,
+            history: history.clone(),
+            learner: learner.clone(),
+            input_loudness: input_loudness.clone(),
+            output_loudness: output_loudness.clone(),
+            param_ids: params
+                .param_map()
+                .into_iter()
+                .map(|(id, ptr, _)| (ptr, id))
+                .collect(),
+            gestures: HashMap::new(),
+        }
+        .build(cx);
+
+        VStack::new(cx, |cx| {
+            HStack::new(cx, |cx| {
+                button(cx, "Undo", || EditorEvent::Undo).disabled(
+                    Data::history.map(|history| !history.lock().unwrap().can_undo()),
+                );
+                button(cx, "Redo", || EditorEvent::Redo).disabled(
+                    Data::history.map(|history| !history.lock().unwrap().can_redo()),
+                );
+                Label::new(
+                    cx,
+                    Data::params.map(|params| {
+                        format!("Editing {:?}", params.ab_slots.read().unwrap().active)
+                    }),
+                );
+                button(cx, "A/B", || EditorEvent::SwitchAb);
+                button(cx, "Copy to Other", || EditorEvent::CopyAb);
+                button(cx, "Swap", || EditorEvent::SwapAb);
+            })
+            .height(Auto)
+            .col_between(Pixels(8.0));
+
+            Label::new(
+                cx,
+                Data::input_loudness.map(|readings| loudness_text("In", readings)),
+            );
+            Label::new(
+                cx,
+                Data::output_loudness.map(|readings| loudness_text("Out", readings)),
+            );
+
+            for band in 0..MAX_MBCS {
+                Label::new(
+                    cx,
+                    Data::learner
+                        .map(move |learner| stats_text(band, learner.lock().unwrap().stats(band))),
+                );
+            }
+            button(cx, "Apply Learned", || EditorEvent::ApplyLearned).disabled(
+                Data::learner.map(|learner| !learner.lock().unwrap().has_proposal()),
+            );
+
+            ScrollView::new(cx, 0.0, 0.0, false, true, |cx| {
+                GenericUi::new(cx, Data::params)
+                    .width(Percentage(100.0))
+                    .height(Auto)
+                    .child_top(Pixels(5.0));
+            })
+            .width(Percentage(100.0));
+        })
+        .row_between(Pixels(8.0))
+        .child_space(Pixels(10.0));
+    })
+}
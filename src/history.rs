@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+
+use nih_plug::prelude::{ParamSetter, Params};
+
+use crate::state::{AbSlot, AbSlots, Snapshot};
+
+const MAX_UNDO_STEPS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Change {
+    id: String,
+    before: f32,
+    after: f32,
+}
+
+/// Undo/redo stack of the editor's parameter edits, values are normalized.
+///
+/// A step is either one gesture on one parameter or a change of many parameters at once, like
+/// loading an A/B slot. Undoing hands back the values to set, `apply` sends them to the host.
+#[derive(Debug, Default)]
+pub struct History {
+    undo: VecDeque<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+    // values from before the gestures that are still running
+    pending: Snapshot,
+}
+
+impl History {
+    /// call when a gesture starts, `value` is the parameter's value before the edit
+    pub fn begin(&mut self, id: &str, value: f32) {
+        self.pending.entry(id.to_string()).or_insert(value);
+    }
+
+    /// call when the gesture ends, everything in between becomes a single step
+    pub fn end(&mut self, id: &str, value: f32) {
+        if let Some(before) = self.pending.remove(id) {
+            self.push(vec![Change {
+                id: id.to_string(),
+                before,
+                after: value,
+            }]);
+        }
+    }
+
+    /// records the difference between two snapshots as one step
+    pub fn push_snapshots(&mut self, before: &Snapshot, after: &Snapshot) {
+        self.push(
+            after
+                .iter()
+                .filter_map(|(id, after)| {
+                    Some(Change {
+                        id: id.clone(),
+                        before: *before.get(id)?,
+                        after: *after,
+                    })
+                })
+                .collect(),
+        );
+    }
+
+    fn push(&mut self, mut changes: Vec<Change>) {
+        changes.retain(|change| change.before != change.after);
+        if changes.is_empty() {
+            return;
+        }
+
+        if self.undo.len() == MAX_UNDO_STEPS {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(changes);
+        self.redo.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// returns the values to set to get back to before the last step
+    pub fn undo(&mut self) -> Option<Snapshot> {
+        let changes = self.undo.pop_back()?;
+        let values = changes
+            .iter()
+            .map(|change| (change.id.clone(), change.before))
+            .collect();
+        self.redo.push(changes);
+
+        Some(values)
+    }
+
+    pub fn redo(&mut self) -> Option<Snapshot> {
+        let changes = self.redo.pop()?;
+        let values = changes
+            .iter()
+            .map(|change| (change.id.clone(), change.after))
+            .collect();
+        self.undo.push_back(changes);
+
+        Some(values)
+    }
+}
+
+/// Normalized values of all parameters.
+pub fn snapshot(params: &impl Params) -> Snapshot {
+    params
+        .param_map()
+        .into_iter()
+        .map(|(id, ptr, _)| (id, unsafe { ptr.unmodulated_normalized_value() }))
+        .collect()
+}
+
+/// Sets every parameter in `values` through the host, so the changes show up in its automation
+/// and undo like any other edit.
+pub fn apply(params: &impl Params, setter: &ParamSetter, values: &Snapshot) {
+    for (id, ptr, _) in params.param_map() {
+        let Some(value) = values.get(&id) else {
+            continue;
+        };
+
+        unsafe {
+            setter.raw_context.raw_begin_set_parameter(ptr);
+            setter.raw_context.raw_set_parameter_normalized(ptr, *value);
+            setter.raw_context.raw_end_set_parameter(ptr);
+        }
+    }
+}
+
+/// `apply` recorded as a single undo step.
+pub fn load(params: &impl Params, setter: &ParamSetter, history: &mut History, values: &Snapshot) {
+    let before = snapshot(params);
+    apply(params, setter, values);
+    history.push_snapshots(&before, values);
+}
+
+impl AbSlot {
+    fn index(&self) -> usize {
+        match self {
+            AbSlot::A => 0,
+            AbSlot::B => 1,
+        }
+    }
+
+    pub fn other(&self) -> Self {
+        match self {
+            AbSlot::A => AbSlot::B,
+            AbSlot::B => AbSlot::A,
+        }
+    }
+}
+
+// `live` is always the current parameter state, it stands in for the active slot. the returned
+// values, if any, have to be loaded to match the new active slot
+impl AbSlots {
+    pub fn switch(&mut self, live: Snapshot) -> Option<Snapshot> {
+        self.snapshots[self.active.index()] = Some(live);
+        self.active = self.active.other();
+
+        self.snapshots[self.active.index()].clone()
+    }
+
+    /// copies `from` over the other slot, does nothing if `from` was never filled
+    pub fn copy(&mut self, from: AbSlot, live: Snapshot) -> Option<Snapshot> {
+        let values = if from == self.active {
+            live
+        } else {
+            self.snapshots[from.index()].clone()?
+        };
+        let to = from.other();
+        self.snapshots[to.index()] = Some(values.clone());
+
+        (to == self.active).then_some(values)
+    }
+
+    /// exchanges the settings of both slots, the active slot stays the same
+    pub fn swap(&mut self, live: Snapshot) -> Option<Snapshot> {
+        self.snapshots[self.active.index()] = Some(live);
+        self.snapshots.swap(0, 1);
+
+        self.snapshots[self.active.index()].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(entries: &[(&str, f32)]) -> Snapshot {
+        entries
+            .iter()
+            .map(|(id, value)| (id.to_string(), *value))
+            .collect()
+    }
+
+    #[test]
+    fn undo_redo_gestures() {
+        let mut history = History::default();
+
+        // a drag sends many values, only the start and the end count
+        history.begin("ratio_1", 0.1);
+        history.end("ratio_1", 0.4);
+        history.begin("gain_2", 0.5);
+        history.end("gain_2", 0.5);
+        history.begin("mix", 1.0);
+        history.end("mix", 0.25);
+
+        assert_eq!(history.undo(), Some(values(&[("mix", 1.0)])));
+        assert_eq!(history.undo(), Some(values(&[("ratio_1", 0.1)])));
+        assert!(!history.can_undo());
+        assert_eq!(history.redo(), Some(values(&[("ratio_1", 0.4)])));
+
+        // a new edit drops what was left to redo
+        history.begin("q_1", 0.2);
+        history.end("q_1", 0.3);
+        assert!(!history.can_redo());
+        assert_eq!(history.undo(), Some(values(&[("q_1", 0.2)])));
+        assert_eq!(history.undo(), Some(values(&[("ratio_1", 0.1)])));
+        assert_eq!(history.undo(), None);
+    }
+
+    #[test]
+    fn undo_is_bounded() {
+        let mut history = History::default();
+        for step in 0..MAX_UNDO_STEPS + 10 {
+            history.begin("mix", step as f32);
+            history.end("mix", step as f32 + 1.0);
+        }
+
+        let mut steps = 0;
+        while history.undo().is_some() {
+            steps += 1;
+        }
+        assert_eq!(steps, MAX_UNDO_STEPS);
+    }
+
+    #[test]
+    fn ab_slots() {
+        let a = values(&[("ratio_1", 0.1), ("mix", 1.0)]);
+        let b = values(&[("ratio_1", 0.7), ("mix", 0.5)]);
+        let mut slots = AbSlots::default();
+
+        // B starts out empty, switching keeps the live settings
+        assert_eq!(slots.switch(a.clone()), None);
+        assert_eq!(slots.active, AbSlot::B);
+
+        assert_eq!(slots.switch(b.clone()), Some(a.clone()));
+        assert_eq!(slots.active, AbSlot::A);
+
+        assert_eq!(slots.swap(a.clone()), Some(b.clone()));
+        assert_eq!(slots.snapshots, [Some(b.clone()), Some(a.clone())]);
+
+        // copying onto the inactive slot changes nothing live
+        assert_eq!(slots.copy(AbSlot::A, b.clone()), None);
+        assert_eq!(slots.snapshots[1], Some(b.clone()));
+
+        slots.snapshots[1] = Some(a.clone());
+        assert_eq!(slots.copy(AbSlot::B, b.clone()), Some(a.clone()));
+
+        let mut history = History::default();
+        history.push_snapshots(&b, &a);
+        assert_eq!(history.undo(), Some(b));
+    }
+}
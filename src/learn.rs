@@ -0,0 +1,369 @@
+use nih_plug::prelude::{util, ParamSetter};
+
+use crate::history::{self, History};
+use crate::{OpenMbcParams, MAX_MBCS, THRESHOLD_MIN_DB};
+
+// short enough to follow what the detector sees, long enough for a meaningful rms
+const BLOCK_MSEC: f32 = 50.0;
+// quieter blocks are silence and would only drag the statistics down
+const SILENCE_DB: f32 = -70.0;
+const BINS_PER_DB: f32 = 2.0;
+const HISTOGRAM_BINS: usize = 200;
+const BISECT_STEPS: usize = 24;
+
+/// Percentiles reported in `BandStats`.
+pub const PERCENTILES: [f32; 4] = [0.1, 0.5, 0.9, 0.99];
+
+/// Peak and rms of every band over one block.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockLevels {
+    pub peak_db: [f32; MAX_MBCS],
+    pub rms_db: [f32; MAX_MBCS],
+}
+
+pub enum LearnTask {
+    Reset,
+    Block(BlockLevels),
+    /// `ratios` are the bands' current ratios, `target_db` is the average reduction to aim for
+    Propose {
+        band_count: usize,
+        ratios: [f32; MAX_MBCS],
+        target_db: f32,
+    },
+}
+
+/// Audio thread side, sums the split bands up into blocks for the background task.
+pub struct LearnCollector {
+    block_len: usize,
+    pos: usize,
+    sum_sq: [f32; MAX_MBCS],
+    peak: [f32; MAX_MBCS],
+    finished: Option<BlockLevels>,
+}
+
+impl Default for LearnCollector {
+    fn default() -> Self {
+        Self {
+            block_len: 1,
+            pos: 0,
+            sum_sq: [0.0; MAX_MBCS],
+            peak: [0.0; MAX_MBCS],
+            finished: None,
+        }
+    }
+}
+
+impl LearnCollector {
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.block_len = ((BLOCK_MSEC * sample_rate / 1000.0) as usize).max(1);
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.pos = 0;
+        self.sum_sq = [0.0; MAX_MBCS];
+        self.peak = [0.0; MAX_MBCS];
+        self.finished = None;
+    }
+
+    pub fn add(&mut self, band: usize, smp: f32) {
+        self.sum_sq[band] += smp * smp;
+        self.peak[band] = self.peak[band].max(smp.abs());
+    }
+
+    /// call once per frame, after all channels' bands were added
+    pub fn end_frame(&mut self, channels: usize) {
+        self.pos += 1;
+        if self.pos < self.block_len {
+            return;
+        }
+
+        let samples = (self.block_len * channels.max(1)) as f32;
+        self.finished = Some(BlockLevels {
+            peak_db: self.peak.map(util::gain_to_db),
+            rms_db: self
+                .sum_sq
+                .map(|sum_sq| util::gain_to_db((sum_sq / samples).sqrt())),
+        });
+        self.pos = 0;
+        self.sum_sq = [0.0; MAX_MBCS];
+        self.peak = [0.0; MAX_MBCS];
+    }
+
+    pub fn take_finished(&mut self) -> Option<BlockLevels> {
+        self.finished.take()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Histogram {
+    min_db: f32,
+    counts: [u32; HISTOGRAM_BINS],
+}
+
+impl Histogram {
+    fn new(min_db: f32) -> Self {
+        Self {
+            min_db,
+            counts: [0; HISTOGRAM_BINS],
+        }
+    }
+
+    fn add(&mut self, db: f32) {
+        let bin = ((db - self.min_db) * BINS_PER_DB).max(0.0) as usize;
+        self.counts[bin.min(HISTOGRAM_BINS - 1)] += 1;
+    }
+
+    fn total(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    // bin centers with their counts
+    fn iter(&self) -> impl Iterator<Item = (f32, u32)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(bin, count)| (self.min_db + (bin as f32 + 0.5) / BINS_PER_DB, *count))
+    }
+
+    fn percentile(&self, fraction: f32) -> f32 {
+        let target = (fraction * self.total() as f32) as u32;
+        let mut acc = 0;
+        for (db, count) in self.iter() {
+            acc += count;
+            if acc > target {
+                return db;
+            }
+        }
+
+        self.min_db + HISTOGRAM_BINS as f32 / BINS_PER_DB
+    }
+}
+
+struct BandHistograms {
+    peak: Histogram,
+    rms: Histogram,
+    crest: Histogram,
+}
+
+impl Default for BandHistograms {
+    fn default() -> Self {
+        Self {
+            peak: Histogram::new(SILENCE_DB),
+            rms: Histogram::new(SILENCE_DB),
+            crest: Histogram::new(0.0),
+        }
+    }
+}
+
+/// Learned levels of a band in dB, one value per entry in `PERCENTILES`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BandStats {
+    pub peak: [f32; PERCENTILES.len()],
+    pub rms: [f32; PERCENTILES.len()],
+    pub crest: [f32; PERCENTILES.len()],
+}
+
+/// Background task side, collects the blocks and turns them into threshold proposals.
+#[derive(Default)]
+pub struct Learner {
+    bands: [BandHistograms; MAX_MBCS],
+    proposal: Option<[Option<f32>; MAX_MBCS]>,
+}
+
+impl Learner {
+    pub fn run(&mut self, task: LearnTask) {
+        match task {
+            LearnTask::Reset => *self = Self::default(),
+            LearnTask::Block(levels) => {
+                for (band, (peak_db, rms_db)) in self
+                    .bands
+                    .iter_mut()
+                    .zip(levels.peak_db.into_iter().zip(levels.rms_db))
+                {
+                    if rms_db < SILENCE_DB {
+                        continue;
+                    }
+                    band.peak.add(peak_db);
+                    band.rms.add(rms_db);
+                    band.crest.add(peak_db - rms_db);
+                }
+            }
+            LearnTask::Propose {
+                band_count,
+                ratios,
+                target_db,
+            } => {
+                self.proposal = Some(std::array::from_fn(|idx| {
+                    (idx < band_count)
+                        .then(|| propose_threshold(&self.bands[idx].peak, ratios[idx], target_db))
+                        .flatten()
+                }));
+            }
+        }
+    }
+
+    /// `None` until the band saw some signal
+    pub fn stats(&self, band: usize) -> Option<BandStats> {
+        let band = &self.bands[band];
+        if band.peak.total() == 0 {
+            return None;
+        }
+
+        Some(BandStats {
+            peak: PERCENTILES.map(|fraction| band.peak.percentile(fraction)),
+            rms: PERCENTILES.map(|fraction| band.rms.percentile(fraction)),
+            crest: PERCENTILES.map(|fraction| band.crest.percentile(fraction)),
+        })
+    }
+
+    /// set once learning was switched off, until the editor applies it
+    pub fn has_proposal(&self) -> bool {
+        self.proposal.is_some()
+    }
+
+    /// proposed threshold per band, `None` for bands that can't reach the target
+    pub fn take_proposal(&mut self) -> Option<[Option<f32>; MAX_MBCS]> {
+        self.proposal.take()
+    }
+}
+
+// the detector follows the peaks, so the block peaks stand in for its level. the static curve is
+// used without the knee
+fn propose_threshold(peaks: &Histogram, ratio: f32, target_db: f32) -> Option<f32> {
+    let total = peaks.total();
+    if ratio <= 1.0 || total == 0 {
+        return None;
+    }
+
+    let slope = 1.0 - 1.0 / ratio;
+    let avg_reduction = |threshold: f32| {
+        peaks
+            .iter()
+            .map(|(db, count)| (db - threshold).max(0.0) * slope * count as f32)
+            .sum::<f32>()
+            / total as f32
+    };
+
+    // the average reduction only shrinks as the threshold goes up, a band that falls short even
+    // at the bottom of the range is too quiet for the target
+    let (mut low, mut high) = (THRESHOLD_MIN_DB, 0.0);
+    if avg_reduction(low) < target_db {
+        return None;
+    }
+    for _ in 0..BISECT_STEPS {
+        let mid = (low + high) / 2.0;
+        if avg_reduction(mid) > target_db {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    Some((high * 10.0).round() / 10.0)
+}
+
+/// Sets the proposed thresholds through the host as one undo step.
+pub fn apply_proposal(
+    params: &OpenMbcParams,
+    setter: &ParamSetter,
+    history: &mut History,
+    thresholds: &[Option<f32>; MAX_MBCS],
+) {
+    let before = history::snapshot(params);
+    for (comp, threshold) in params.comps.iter().zip(thresholds) {
+        if let Some(threshold) = threshold {
+            setter.begin_set_parameter(&comp.threshold);
+            setter.set_parameter(&comp.threshold, *threshold);
+            setter.end_set_parameter(&comp.threshold);
+        }
+    }
+    history.push_snapshots(&before, &history::snapshot(params));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collector_levels() {
+        let mut collector = LearnCollector::default();
+        collector.set_sample_rate(48000.0);
+
+        // a full scale square wave in band 0, a -20 dB sine in band 1
+        let mut levels = None;
+        for idx in 0..2400 {
+            let sine =
+                util::db_to_gain(-20.0) * (std::f32::consts::TAU * (idx % 48) as f32 / 48.0).sin();
+            for _ in 0..2 {
+                collector.add(0, if idx % 2 == 0 { 1.0 } else { -1.0 });
+                collector.add(1, sine);
+            }
+            collector.end_frame(2);
+            levels = levels.or(collector.take_finished());
+        }
+
+        let levels = levels.unwrap();
+        assert!(levels.peak_db[0].abs() < 0.01 && levels.rms_db[0].abs() < 0.01);
+        assert!((levels.peak_db[1] + 20.0).abs() < 0.1);
+        assert!((levels.rms_db[1] + 23.01).abs() < 0.1);
+        assert!(levels.rms_db[2] < SILENCE_DB);
+    }
+
+    #[test]
+    fn proposal_hits_target() {
+        let mut learner = Learner::default();
+        learner.run(LearnTask::Reset);
+        for block in 0..1000 {
+            // peaks alternating between -20 and -30 dB, with a 10 dB crest
+            let peak_db = if block % 2 == 0 { -20.0 } else { -30.0 };
+            learner.run(LearnTask::Block(BlockLevels {
+                peak_db: [peak_db; MAX_MBCS],
+                rms_db: [peak_db - 10.0, peak_db - 10.0, -100.0, -100.0, -100.0],
+            }));
+        }
+
+        let stats = learner.stats(0).unwrap();
+        assert!((stats.peak[0] + 30.0).abs() <= 0.5);
+        assert!((stats.peak[3] + 20.0).abs() <= 0.5);
+        assert!((stats.crest[2] - 10.0).abs() <= 0.5);
+        assert_eq!(learner.stats(2), None);
+
+        learner.run(LearnTask::Propose {
+            band_count: 2,
+            ratios: [4.0, 1.0, 4.0, 4.0, 4.0],
+            target_db: 3.0,
+        });
+        let proposal = learner.take_proposal().unwrap();
+
+        // only the -20 dB half gets compressed: (-20 - t) * 0.75 / 2 = 3 -> t = -28
+        assert!((proposal[0].unwrap() + 28.0).abs() < 0.5);
+        // 1:1 never reduces, and bands past the band count are left alone
+        assert_eq!(proposal[1..], [None; MAX_MBCS - 1]);
+        assert!(learner.take_proposal().is_none());
+    }
+
+    #[test]
+    fn quiet_band_gets_no_proposal() {
+        let mut learner = Learner::default();
+        learner.run(LearnTask::Reset);
+        for _ in 0..100 {
+            // peaks 2 dB over the lowest threshold only make 1.5 dB of reduction at 4:1
+            learner.run(LearnTask::Block(BlockLevels {
+                peak_db: [-20.0, THRESHOLD_MIN_DB + 2.0, -100.0, -100.0, -100.0],
+                rms_db: [-30.0, THRESHOLD_MIN_DB - 4.0, -100.0, -100.0, -100.0],
+            }));
+        }
+        assert!(learner.stats(1).is_some());
+
+        learner.run(LearnTask::Propose {
+            band_count: 2,
+            ratios: [4.0; MAX_MBCS],
+            target_db: 3.0,
+        });
+        let proposal = learner.take_proposal().unwrap();
+
+        assert!((proposal[0].unwrap() + 24.0).abs() < 0.5);
+        assert_eq!(proposal[1], None);
+    }
+}
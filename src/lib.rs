@@ -1,11 +1,38 @@
 use cute_dsp::filters::Biquad;
 use nih_plug::prelude::*;
-use std::sync::Arc;
-
+use nih_plug_vizia::ViziaState;
+use std::sync::{Arc, Mutex, RwLock};
 
 mod compressor;
+mod crossover;
+mod ducking;
+mod editor;
+mod history;
+mod learn;
+mod loudness;
+#[cfg(test)]
+mod measure;
+mod migration;
+mod output;
+mod oversampling;
+mod state;
+mod tempo;
+mod units;
 
-use compressor::Compressor;
+use compressor::{
+    Compressor, DeEssShape, DeEsser, DynamicEq, EqShape, Gate, LowerMode, SidechainEqMode,
+    SidechainFilter, TransientShaper,
+};
+use crossover::Crossover;
+use ducking::Ducker;
+use history::History;
+use learn::{LearnCollector, LearnTask, Learner};
+use loudness::LoudnessMeter;
+use output::{Limiter, OutputMode};
+use oversampling::{DelayLine, DryPath, Oversampler, OversamplingFactor, OversamplingFilter};
+use state::AbSlots;
+use tempo::{HostTempo, NoteValue};
+use units::{freq_range, s2v_f32_ms_then_s, time_range, v2s_f32_ms_then_s};
 
 // This is a shortened version of the gain example with most comments removed, check out
 // https://github.com/robbert-vdh/nih-plug/blob/master/plugins/examples/gain/src/lib.rs to get
@@ -14,7 +41,38 @@ use compressor::Compressor;
 struct OpenMbc {
     params: Arc<OpenMbcParams>,
     sample_rate: f32,
-    comp_filt_state: [CompFilter; MAX_MBCS],
+    channels: usize,
+    links: [LinkGroup; MAX_CHANNELS],
+    // keeps excluded channels in line with the processed ones
+    excluded_delays: [DelayLine<MAX_OVERSAMPLING_LATENCY>; MAX_CHANNELS],
+    crossovers: [Crossover; MAX_CHANNELS],
+    // bands in use, a new band count waits for `band_switch` to fade the output out
+    band_count: usize,
+    band_switching: bool,
+    band_switch: Smoother<f32>,
+    comp_filt_state: [[CompFilter; MAX_MBCS]; MAX_CHANNELS],
+    band_mix: [BandMix; MAX_MBCS],
+    limiter: Limiter,
+    ducker: Ducker,
+    input_meter: LoudnessMeter,
+    // the bands ahead of the match gain, what the match is worked out from
+    match_meter: LoudnessMeter,
+    // what leaves the plugin, after the output stage
+    output_meter: LoudnessMeter,
+    // in dB
+    loudness_match: Smoother<f32>,
+    learning: bool,
+    learn_collector: LearnCollector,
+    learner: Arc<Mutex<Learner>>,
+    // the editor's undo steps, kept here so they outlive the editor window
+    history: Arc<Mutex<History>>,
+    oversampling: (OversamplingFactor, OversamplingFilter),
+    latency_samples: u32,
+    // consecutive samples of silent input, the bands are skipped once the tail played out
+    silent_samples: usize,
+    // worked out from the state the bands were in when the input went silent
+    tail: u32,
+    skipping: bool,
 }
 
 #[derive(Params)]
@@ -25,24 +83,209 @@ struct OpenMbcParams {
     /// gain parameter is stored as linear gain while the values are displayed in decibels.
     #[nested(array, group = "Comps")]
     pub comps: [CompParams; MAX_MBCS],
+
+    #[id = "band_count"]
+    pub band_count: IntParam,
+
+    #[nested(array, group = "Crossovers")]
+    pub crossovers: [CrossoverParams; MAX_MBCS - 1],
+
+    #[nested(array, group = "Channels")]
+    pub channels: [ChannelParams; MAX_CHANNELS],
+
+    /// global dry/wet, applied before the output stage
+    #[id = "mix"]
+    pub mix: FloatParam,
+
+    /// gain reduction in dB when a note-on ducks the bands that have `duck` enabled
+    #[id = "duck_depth"]
+    pub duck_depth: FloatParam,
+
+    #[id = "duck_attack"]
+    pub duck_attack: FloatParam,
+
+    #[id = "duck_hold"]
+    pub duck_hold: FloatParam,
+
+    #[id = "duck_release"]
+    pub duck_release: FloatParam,
+
+    /// shape of the attack and release segments, 1 is linear
+    #[id = "duck_curve"]
+    pub duck_curve: FloatParam,
+
+    #[id = "oversampling"]
+    pub oversampling: EnumParam<OversamplingFactor>,
+
+    #[id = "oversampling_filter"]
+    pub oversampling_filter: EnumParam<OversamplingFilter>,
+
+    #[id = "output_mode"]
+    pub output_mode: EnumParam<OutputMode>,
+
+    #[id = "ceiling"]
+    pub ceiling: FloatParam,
+
+    #[id = "limiter_release"]
+    pub limiter_release: FloatParam,
+
+    /// follow the input's short term loudness at the output, to judge the processing without
+    /// the louder version winning
+    #[id = "loudness_match"]
+    pub loudness_match: BoolParam,
+
+    /// collect band levels while enabled, thresholds are proposed once it's switched off
+    #[id = "learn"]
+    pub learn: BoolParam,
+
+    /// average gain reduction the proposed thresholds aim for
+    #[id = "learn_target"]
+    pub learn_target: FloatParam,
+
+    /// schema of the persisted fields below, see `state::STATE_VERSION`
+    #[persist = "state_version"]
+    pub state_version: RwLock<u32>,
+
+    /// the editor's size, kept up to date by the editor itself
+    #[persist = "editor_state"]
+    pub editor_state: Arc<ViziaState>,
+
+    #[persist = "ab_slots"]
+    pub ab_slots: RwLock<AbSlots>,
 }
 
 const MAX_MBCS: usize = 5;
+// 7.1 is the widest layout we offer
+const MAX_CHANNELS: usize = 8;
+// the surround layouts are ordered L R C LFE Ls Rs, with Lb Rb added for 7.1
+const LFE_CHANNEL: usize = 3;
+const LINK_GROUPS: usize = 3;
 const FREQ_RANGE_MIN: f32 = 20.0;
 const FREQ_RANGE_MAX: f32 = 20_000.0;
+const DEESS_FREQ_MIN: f32 = 4_000.0;
+const DEESS_FREQ_MAX: f32 = 10_000.0;
+const THRESHOLD_MIN_DB: f32 = -60.0;
+// the lower and gate thresholds sit under the main one, they need to reach further down
+const LOW_THRESHOLD_MIN_DB: f32 = -80.0;
+const CROSSOVER_DEFAULTS: [f32; MAX_MBCS - 1] = [120.0, 500.0, 2_000.0, 6_000.0];
+// solo/mute/bypass fade time
+const BAND_FADE_MSEC: f32 = 5.0;
+// longest linear phase oversampling latency, 28 samples at 8x
+const MAX_OVERSAMPLING_LATENCY: usize = 32;
+const LOUDNESS_MATCH_MSEC: f32 = 200.0;
+const LOUDNESS_MATCH_MAX_DB: f64 = 24.0;
+// -120 dB, input below this counts as silence
+const SILENCE_GAIN: f32 = 1e-6;
+// two release times take an exponential release down to 1% of the reduction, the compressor
+// works out its own from its curve
+const RELEASE_TAILS: f32 = 2.0;
+
+/// How a band turns its detector into gain changes.
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum BandMode {
+    #[default]
+    Compressor,
+    #[name = "De-esser"]
+    DeEsser,
+    #[name = "Dynamic EQ"]
+    DynamicEq,
+    #[name = "Transient Shaper"]
+    TransientShaper,
+}
+
+/// Channels in the same group share one detector per band, excluded channels pass through.
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkGroup {
+    #[default]
+    Independent,
+    #[name = "Group 1"]
+    Group1,
+    #[name = "Group 2"]
+    Group2,
+    #[name = "Group 3"]
+    Group3,
+    Excluded,
+}
+
+impl LinkGroup {
+    fn group(&self) -> Option<usize> {
+        match self {
+            LinkGroup::Group1 => Some(0),
+            LinkGroup::Group2 => Some(1),
+            LinkGroup::Group3 => Some(2),
+            LinkGroup::Independent | LinkGroup::Excluded => None,
+        }
+    }
+}
+
+#[derive(Params)]
+struct ChannelParams {
+    #[id = "link"]
+    pub link: EnumParam<LinkGroup>,
+}
+
+impl ChannelParams {
+    fn new(idx: usize) -> Self {
+        Self {
+            link: EnumParam::new(
+                "Link",
+                if idx == LFE_CHANNEL {
+                    LinkGroup::Excluded
+                } else {
+                    LinkGroup::Independent
+                },
+            ),
+        }
+    }
+}
+
+#[derive(Params)]
+struct CrossoverParams {
+    #[id = "freq"]
+    pub freq: FloatParam,
+}
+
+impl CrossoverParams {
+    fn new(idx: usize) -> Self {
+        Self {
+            freq: FloatParam::new(
+                "Crossover",
+                CROSSOVER_DEFAULTS[idx],
+                freq_range(FREQ_RANGE_MIN, FREQ_RANGE_MAX),
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+        }
+    }
+}
 
 #[derive(Params)]
 struct CompParams {
-    #[id = "enable"]
-    pub enable: BoolParam,
+    /// pass the band through unprocessed
+    #[id = "bypass"]
+    pub bypass: BoolParam,
+
+    #[id = "mute"]
+    pub mute: BoolParam,
+
+    /// follow the MIDI triggered ducking envelope
+    #[id = "duck"]
+    pub duck: BoolParam,
+
+    /// when any band is soloed, only soloed bands are heard
+    #[id = "solo"]
+    pub solo: BoolParam,
+
+    #[id = "mode"]
+    pub mode: EnumParam<BandMode>,
 
     #[id = "center_freq"]
     pub center_freq: FloatParam,
 
     #[id = "q"]
     pub q: FloatParam,
-    //TODO:
-    #[id = "threshold"]
+    /// in dB, older versions stored linear gain under `threshold`, see `migration`
+    #[id = "threshold_db"]
     pub threshold: FloatParam,
 
     #[id = "ratio"]
@@ -53,22 +296,149 @@ struct CompParams {
     #[id = "release"]
     pub release: FloatParam,
 
+    /// program dependent release, `release` is ignored while enabled
+    #[id = "auto_release"]
+    pub auto_release: BoolParam,
+
+    /// take the attack from `attack_note` at the host tempo
+    #[id = "attack_sync"]
+    pub attack_sync: BoolParam,
+
+    #[id = "attack_note"]
+    pub attack_note: EnumParam<NoteValue>,
+
+    /// take the release from `release_note` at the host tempo
+    #[id = "release_sync"]
+    pub release_sync: BoolParam,
+
+    #[id = "release_note"]
+    pub release_note: EnumParam<NoteValue>,
+
     #[id = "gain"]
     pub gain: FloatParam,
+
+    /// makeup gain on top of `gain` estimated from the compressor settings
+    #[id = "auto_makeup"]
+    pub auto_makeup: BoolParam,
+
+    /// follow the long term average reduction instead of the static estimate
+    #[id = "auto_makeup_adaptive"]
+    pub auto_makeup_adaptive: BoolParam,
+
+    /// parallel compression, the dry part is the band before processing
+    #[id = "mix"]
+    pub mix: FloatParam,
+
+    #[id = "lower_mode"]
+    pub lower_mode: EnumParam<LowerMode>,
+
+    #[id = "lower_threshold_db"]
+    pub lower_threshold: FloatParam,
+
+    #[id = "lower_ratio"]
+    pub lower_ratio: FloatParam,
+
+    #[id = "max_gain"]
+    pub max_gain: FloatParam,
+
+    #[id = "range"]
+    pub range: FloatParam,
+
+    #[id = "hold"]
+    pub hold: FloatParam,
+
+    #[id = "gate_enable"]
+    pub gate_enable: BoolParam,
+
+    #[id = "gate_threshold_db"]
+    pub gate_threshold: FloatParam,
+
+    #[id = "gate_range"]
+    pub gate_range: FloatParam,
+
+    #[id = "gate_attack"]
+    pub gate_attack: FloatParam,
+
+    #[id = "gate_hold"]
+    pub gate_hold: FloatParam,
+
+    #[id = "gate_release"]
+    pub gate_release: FloatParam,
+
+    #[id = "gate_hysteresis"]
+    pub gate_hysteresis: FloatParam,
+
+    #[id = "deess_freq"]
+    pub deess_freq: FloatParam,
+
+    #[id = "deess_shape"]
+    pub deess_shape: EnumParam<DeEssShape>,
+
+    /// reduce the full signal instead of only the sibilant band
+    #[id = "deess_wideband"]
+    pub deess_wideband: BoolParam,
+
+    /// monitor the de-esser's sidechain instead of the output
+    #[id = "deess_listen"]
+    pub deess_listen: BoolParam,
+
+    #[id = "deq_shape"]
+    pub deq_shape: EnumParam<EqShape>,
+
+    /// negative values cut, positive values boost
+    #[id = "deq_range"]
+    pub deq_range: FloatParam,
+
+    #[id = "transient_attack"]
+    pub transient_attack: FloatParam,
+
+    #[id = "transient_sustain"]
+    pub transient_sustain: FloatParam,
+
+    /// key the detector on the full input instead of the band's own signal
+    #[id = "sc_wideband"]
+    pub sc_wideband: BoolParam,
+
+    #[id = "sc_hpf_enable"]
+    pub sc_hpf_enable: BoolParam,
+
+    #[id = "sc_hpf_freq"]
+    pub sc_hpf_freq: FloatParam,
+
+    #[id = "sc_lpf_enable"]
+    pub sc_lpf_enable: BoolParam,
+
+    #[id = "sc_lpf_freq"]
+    pub sc_lpf_freq: FloatParam,
+
+    #[id = "sc_eq_mode"]
+    pub sc_eq_mode: EnumParam<SidechainEqMode>,
+
+    #[id = "sc_eq_freq"]
+    pub sc_eq_freq: FloatParam,
+
+    #[id = "sc_eq_gain"]
+    pub sc_eq_gain: FloatParam,
+
+    #[id = "sc_eq_q"]
+    pub sc_eq_q: FloatParam,
 }
 
 impl Default for CompParams {
     fn default() -> Self {
         Self {
-            enable: BoolParam::new("Enable", false),
+            bypass: BoolParam::new("Bypass", false),
+            mute: BoolParam::new("Mute", false),
+            duck: BoolParam::new("Duck", false),
+            solo: BoolParam::new("Solo", false),
+            mode: EnumParam::new("Mode", BandMode::Compressor),
             center_freq: FloatParam::new(
                 "Center",
                 1000.0,
-                FloatRange::Linear {
-                    min: FREQ_RANGE_MIN,
-                    max: FREQ_RANGE_MAX,
-                },
-            ),
+                freq_range(FREQ_RANGE_MIN, FREQ_RANGE_MAX),
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
             ratio: FloatParam::new(
                 "Ratio",
                 1.0,
@@ -87,10 +457,32 @@ impl Default for CompParams {
             ),
             threshold: FloatParam::new(
                 "Threshold",
+                0.0,
+                FloatRange::Linear {
+                    min: THRESHOLD_MIN_DB,
+                    max: 0.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            attack: FloatParam::new("Attack", 10.0, time_range(1.0, 1000.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            release: FloatParam::new("Release", 100.0, time_range(10.0, 10000.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            auto_release: BoolParam::new("Auto Release", false),
+            attack_sync: BoolParam::new("Attack Sync", false),
+            attack_note: EnumParam::new("Attack Note", NoteValue::N64),
+            release_sync: BoolParam::new("Release Sync", false),
+            release_note: EnumParam::new("Release Note", NoteValue::N8),
+            gain: FloatParam::new(
+                "Gain",
                 util::db_to_gain(0.0),
                 FloatRange::Skewed {
-                    min: util::db_to_gain(-99.0),
-                    max: util::db_to_gain(0.0),
+                    min: util::db_to_gain(-10.0),
+                    max: util::db_to_gain(30.0),
                     factor: 0.7,
                 },
             )
@@ -98,58 +490,476 @@ impl Default for CompParams {
             .with_unit(" dB")
             .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
-            attack: FloatParam::new(
-                "Attack",
-                10.0,
+            auto_makeup: BoolParam::new("Auto Makeup", false),
+            auto_makeup_adaptive: BoolParam::new("Adaptive Makeup", false),
+            mix: FloatParam::new("Mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(BAND_FADE_MSEC))
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+            lower_mode: EnumParam::new("Lower Mode", LowerMode::Off),
+            lower_threshold: FloatParam::new(
+                "Lower Threshold",
+                -40.0,
+                FloatRange::Linear {
+                    min: LOW_THRESHOLD_MIN_DB,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            lower_ratio: FloatParam::new(
+                "Lower Ratio",
+                2.0,
                 FloatRange::Linear {
                     min: 1.0,
-                    max: 1000.0,
+                    max: 10.0,
                 },
             ),
-            release: FloatParam::new(
-                "Release",
-                100.0,
+            max_gain: FloatParam::new(
+                "Max Gain",
+                12.0,
                 FloatRange::Linear {
-                    min: 10.0,
-                    max: 10000.0,
+                    min: 0.0,
+                    max: 36.0,
                 },
-            ),
-            gain: FloatParam::new(
-                "Gain",
-                util::db_to_gain(0.0),
-                FloatRange::Skewed {
-                    min: util::db_to_gain(-10.0),
-                    max: util::db_to_gain(30.0),
-                    factor: 0.7,
+            )
+            .with_unit(" dB"),
+            range: FloatParam::new(
+                "Range",
+                40.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 99.0,
+                },
+            )
+            .with_unit(" dB"),
+            hold: FloatParam::new("Hold", 10.0, time_range(0.0, 500.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            gate_enable: BoolParam::new("Gate Enable", false),
+            gate_threshold: FloatParam::new(
+                "Gate Threshold",
+                -60.0,
+                FloatRange::Linear {
+                    min: LOW_THRESHOLD_MIN_DB,
+                    max: 0.0,
                 },
             )
-            .with_smoother(SmoothingStyle::Logarithmic(50.0))
             .with_unit(" dB")
-            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
-            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            gate_range: FloatParam::new(
+                "Gate Range",
+                40.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 99.0,
+                },
+            )
+            .with_unit(" dB"),
+            gate_attack: FloatParam::new("Gate Attack", 1.0, time_range(0.0, 100.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            gate_hold: FloatParam::new("Gate Hold", 20.0, time_range(0.0, 500.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            gate_release: FloatParam::new("Gate Release", 100.0, time_range(1.0, 5000.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            gate_hysteresis: FloatParam::new(
+                "Gate Hysteresis",
+                6.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB"),
+            deess_freq: FloatParam::new(
+                "De-ess Freq",
+                6_000.0,
+                freq_range(DEESS_FREQ_MIN, DEESS_FREQ_MAX),
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            deess_shape: EnumParam::new("De-ess Shape", DeEssShape::Bandpass),
+            deess_wideband: BoolParam::new("De-ess Wideband", false),
+            deess_listen: BoolParam::new("De-ess Listen", false),
+            deq_shape: EnumParam::new("EQ Shape", EqShape::Bell),
+            deq_range: FloatParam::new(
+                "EQ Range",
+                -6.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB"),
+            transient_attack: FloatParam::new(
+                "Transient Attack",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB"),
+            transient_sustain: FloatParam::new(
+                "Transient Sustain",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB"),
+            sc_wideband: BoolParam::new("SC Wideband", false),
+            sc_hpf_enable: BoolParam::new("SC HPF Enable", false),
+            sc_hpf_freq: FloatParam::new(
+                "SC HPF",
+                100.0,
+                freq_range(FREQ_RANGE_MIN, FREQ_RANGE_MAX),
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            sc_lpf_enable: BoolParam::new("SC LPF Enable", false),
+            sc_lpf_freq: FloatParam::new(
+                "SC LPF",
+                10_000.0,
+                freq_range(FREQ_RANGE_MIN, FREQ_RANGE_MAX),
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            sc_eq_mode: EnumParam::new("SC EQ Mode", SidechainEqMode::Off),
+            sc_eq_freq: FloatParam::new(
+                "SC EQ Freq",
+                1000.0,
+                freq_range(FREQ_RANGE_MIN, FREQ_RANGE_MAX),
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            sc_eq_gain: FloatParam::new(
+                "SC EQ Gain",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB"),
+            sc_eq_q: FloatParam::new(
+                "SC EQ Q",
+                1.0,
+                FloatRange::Linear {
+                    min: 0.1,
+                    max: 10.0,
+                },
+            ),
         }
     }
 }
 
 struct CompFilter {
+    mode: BandMode,
+    deess_wideband: bool,
+    deess_listen: bool,
+    sc_wideband: bool,
+    linked: bool,
+    gate: Gate,
     comp: Compressor,
+    deesser: DeEsser,
+    deq: DynamicEq,
+    transient: TransientShaper,
+    sidechain: SidechainFilter,
     filt: Biquad<f32>,
+    oversampler: Oversampler,
+    key_oversampler: Oversampler,
+    dry_path: DryPath<MAX_OVERSAMPLING_LATENCY>,
+}
+impl CompFilter {
+    fn reset(&mut self) {
+        self.gate.reset();
+        self.comp.reset();
+        self.deesser.reset();
+        self.deq.reset();
+        self.transient.reset();
+        self.sidechain.reset();
+        self.filt.reset();
+        self.oversampler.reset();
+        self.key_oversampler.reset();
+        self.dry_path.reset();
+    }
+
+    //swapping the oversamplers only copies fixed size state, safe on the audio thread
+    fn set_oversampling(&mut self, factor: OversamplingFactor, filter: OversamplingFilter) {
+        self.oversampler = Oversampler::new(factor, filter);
+        self.key_oversampler = Oversampler::new(factor, filter);
+        self.dry_path = DryPath::new(factor, filter);
+    }
+
+    //push the band's parameters down to the compressor, done once per block.
+    //`sample_rate` is the oversampled rate the band runs at, `band_range` the band's edges in Hz
+    fn update(
+        &mut self,
+        params: &CompParams,
+        sample_rate: f32,
+        band_range: (f32, f32),
+        tempo: &HostTempo,
+    ) {
+        self.comp.set_sample_rate(sample_rate);
+        self.gate.set_sample_rate(sample_rate);
+
+        // the dynamic EQ only ever sees its own band, anywhere outside of it would do next to
+        // nothing
+        let center_freq = params
+            .center_freq
+            .value()
+            .max(band_range.0)
+            .min(band_range.1);
+        self.filt
+            .bandpass(center_freq / sample_rate, params.q.value());
+
+        self.mode = params.mode.value();
+        self.deess_wideband = params.deess_wideband.value();
+        self.deess_listen = params.deess_listen.value();
+        self.deesser.update(
+            params.deess_freq.value() / sample_rate,
+            params.deess_shape.value(),
+        );
+        self.deq.update(
+            center_freq / sample_rate,
+            params.q.value(),
+            params.deq_shape.value(),
+            params.deq_range.value(),
+        );
+
+        self.transient.update(
+            sample_rate,
+            params.transient_attack.value(),
+            params.transient_sustain.value(),
+        );
+
+        self.sc_wideband = params.sc_wideband.value();
+        self.sidechain.update(
+            params
+                .sc_hpf_enable
+                .value()
+                .then(|| params.sc_hpf_freq.value() / sample_rate),
+            params
+                .sc_lpf_enable
+                .value()
+                .then(|| params.sc_lpf_freq.value() / sample_rate),
+            params.sc_eq_mode.value(),
+            params.sc_eq_freq.value() / sample_rate,
+            params.sc_eq_gain.value(),
+            params.sc_eq_q.value(),
+        );
+
+        let solver = self.comp.solver_mut();
+
+        solver.threshold = params.threshold.value();
+        solver.update_ratio(params.ratio.value());
+        solver.update_attack(if params.attack_sync.value() {
+            params.attack_note.value().to_msec(tempo)
+        } else {
+            params.attack.value()
+        });
+        solver.update_release(if params.release_sync.value() {
+            params.release_note.value().to_msec(tempo)
+        } else {
+            params.release.value()
+        });
+        solver.update_auto_release(params.auto_release.value());
+
+        solver.lower_threshold = params.lower_threshold.value();
+        solver.update_lower_mode(params.lower_mode.value());
+        solver.update_lower_ratio(params.lower_ratio.value());
+        solver.update_max_gain(params.max_gain.value());
+        solver.update_range(params.range.value());
+        solver.update_hold(params.hold.value());
+        self.comp.update_auto_makeup(
+            params.auto_makeup.value(),
+            params.auto_makeup_adaptive.value(),
+        );
+
+        self.gate.enabled = params.gate_enable.value();
+        self.gate.threshold = params.gate_threshold.value();
+        self.gate.hysteresis = params.gate_hysteresis.value();
+        self.gate.update_range(params.gate_range.value());
+        self.gate.update_attack(params.gate_attack.value());
+        self.gate.update_hold(params.gate_hold.value());
+        self.gate.update_release(params.gate_release.value());
+    }
+
+    // how long the band keeps moving once its input went silent: the gate closes over its hold
+    // and release, then the band's own detector lets go
+    fn tail_msec(&self) -> f32 {
+        let gate = if self.gate.enabled {
+            self.gate.hold_msec() + RELEASE_TAILS * self.gate.release_msec()
+        } else {
+            0.0
+        };
+        let band = match self.mode {
+            // the de-esser and the dynamic EQ follow the compressor's reduction
+            BandMode::Compressor | BandMode::DeEsser | BandMode::DynamicEq => {
+                self.comp.hold_msec() + self.comp.release_tail_msec()
+            }
+            BandMode::TransientShaper => RELEASE_TAILS * self.transient.release_tail_msec(),
+        };
+
+        gate + band
+    }
+
+    /// returns the processed band and the dry band, matched to it in delay and phase
+    fn process(&mut self, smp: f32, key: f32) -> (f32, f32) {
+        let dry = self.dry_path.process(smp);
+        let mut smps = self.oversampler.upsample(smp);
+        let keys = self.key_oversampler.upsample(key);
+
+        for (smp, key) in smps.iter_mut().zip(keys).take(self.oversampler.factor()) {
+            *smp = self.process_band(*smp, key);
+        }
+
+        (self.oversampler.downsample(&mut smps), dry)
+    }
+
+    /// `key` is the unsplit signal for wideband keying or the link group's key, the detector
+    /// only listens to it in those cases
+    fn process_band(&mut self, smp: f32, key: f32) -> f32 {
+        let smp = self.gate.process(smp, None);
+        let key = if self.sc_wideband || self.linked {
+            key
+        } else {
+            smp
+        };
+
+        match self.mode {
+            BandMode::Compressor => {
+                let detector = self.sidechain.process(key);
+                self.comp.process(smp, Some(detector))
+            }
+            BandMode::DeEsser => {
+                let (detector, sibilance) = self.deesser.process(smp, key);
+                let detector = self.sidechain.process(detector);
+
+                if self.deess_listen {
+                    detector
+                } else if self.deess_wideband {
+                    self.comp.process(smp, Some(detector))
+                } else {
+                    (smp - sibilance) + self.comp.process(sibilance, Some(detector))
+                }
+            }
+            BandMode::DynamicEq => {
+                // the band's bandpass doubles as the detector for the EQ
+                let detector = self.sidechain.process(self.filt.process(key));
+                let reduction_db = self.comp.get_reduction(detector);
+                self.deq.process(smp, reduction_db)
+            }
+            BandMode::TransientShaper => {
+                let detector = self.sidechain.process(key);
+                self.transient.process(smp, detector)
+            }
+        }
+    }
 }
+
 impl Default for CompFilter {
     fn default() -> Self {
         Self {
+            mode: BandMode::default(),
+            deess_wideband: false,
+            deess_listen: false,
+            sc_wideband: false,
+            linked: false,
+            gate: Gate::new(0.0),
             comp: Compressor::new(0.0),
+            deesser: DeEsser::default(),
+            deq: DynamicEq::default(),
+            transient: TransientShaper::default(),
+            sidechain: SidechainFilter::default(),
             filt: Biquad::<f32>::new(true),
+            oversampler: Oversampler::default(),
+            key_oversampler: Oversampler::default(),
+            dry_path: DryPath::default(),
         }
     }
 }
 
+/// Solo/mute level and bypass crossfade of a band, shared by all channels.
+struct BandMix {
+    level: Smoother<f32>,
+    wet: Smoother<f32>,
+}
+
+impl Default for BandMix {
+    fn default() -> Self {
+        let level = Smoother::new(SmoothingStyle::Linear(BAND_FADE_MSEC));
+        let wet = Smoother::new(SmoothingStyle::Linear(BAND_FADE_MSEC));
+        level.reset(1.0);
+        wet.reset(1.0);
+
+        Self { level, wet }
+    }
+}
+
+impl BandMix {
+    fn targets(params: &CompParams, any_solo: bool) -> (f32, f32) {
+        let audible = !params.mute.value() && (!any_solo || params.solo.value());
+
+        (
+            if audible { 1.0 } else { 0.0 },
+            if params.bypass.value() { 0.0 } else { 1.0 },
+        )
+    }
+
+    fn update(&self, params: &CompParams, any_solo: bool, sample_rate: f32) {
+        let (level, wet) = Self::targets(params, any_solo);
+
+        self.level.set_target(sample_rate, level);
+        self.wet.set_target(sample_rate, wet);
+    }
+
+    // jump straight to the targets, no fade after a reset
+    fn reset(&self, params: &CompParams, any_solo: bool) {
+        let (level, wet) = Self::targets(params, any_solo);
+
+        self.level.reset(level);
+        self.wet.reset(wet);
+    }
+}
+
 impl Default for OpenMbc {
     fn default() -> Self {
         Self {
             params: Arc::new(OpenMbcParams::default()),
             sample_rate: 0.0,
-            comp_filt_state: std::array::from_fn(|_| CompFilter::default()),
+            channels: 2,
+            links: [LinkGroup::default(); MAX_CHANNELS],
+            excluded_delays: std::array::from_fn(|_| DelayLine::default()),
+            crossovers: std::array::from_fn(|_| Crossover::default()),
+            band_count: MAX_MBCS,
+            band_switching: false,
+            band_switch: Smoother::new(SmoothingStyle::Linear(BAND_FADE_MSEC)),
+            comp_filt_state: std::array::from_fn(|_| {
+                std::array::from_fn(|_| CompFilter::default())
+            }),
+            band_mix: std::array::from_fn(|_| BandMix::default()),
+            limiter: Limiter::default(),
+            ducker: Ducker::default(),
+            input_meter: LoudnessMeter::default(),
+            match_meter: LoudnessMeter::default(),
+            output_meter: LoudnessMeter::default(),
+            loudness_match: Smoother::new(SmoothingStyle::Linear(LOUDNESS_MATCH_MSEC)),
+            learning: false,
+            learn_collector: LearnCollector::default(),
+            learner: Arc::new(Mutex::new(Learner::default())),
+            history: Arc::new(Mutex::new(History::default())),
+            oversampling: (OversamplingFactor::default(), OversamplingFilter::default()),
+            latency_samples: 0,
+            silent_samples: 0,
+            tail: 0,
+            skipping: false,
         }
     }
 }
@@ -161,6 +971,80 @@ impl Default for OpenMbcParams {
             // to treat these kinds of parameters as if we were dealing with decibels. Storing this
             // as decibels is easier to work with, but requires a conversion for every sample.
             comps: std::array::from_fn(|_| CompParams::default()),
+            band_count: IntParam::new(
+                "Bands",
+                MAX_MBCS as i32,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_MBCS as i32,
+                },
+            ),
+            crossovers: std::array::from_fn(CrossoverParams::new),
+            channels: std::array::from_fn(ChannelParams::new),
+            mix: FloatParam::new("Mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(BAND_FADE_MSEC))
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage()),
+            duck_depth: FloatParam::new(
+                "Duck Depth",
+                12.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 60.0,
+                },
+            )
+            .with_unit(" dB"),
+            duck_attack: FloatParam::new("Duck Attack", 2.0, time_range(0.0, 100.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            duck_hold: FloatParam::new("Duck Hold", 20.0, time_range(0.0, 1000.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            duck_release: FloatParam::new("Duck Release", 200.0, time_range(1.0, 2000.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            duck_curve: FloatParam::new(
+                "Duck Curve",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.25,
+                    max: 4.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            ),
+            oversampling: EnumParam::new("Oversampling", OversamplingFactor::X1),
+            oversampling_filter: EnumParam::new("Oversampling Filter", OversamplingFilter::Iir),
+            output_mode: EnumParam::new("Output", OutputMode::Limiter),
+            ceiling: FloatParam::new(
+                "Ceiling",
+                util::db_to_gain(-0.3),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-12.0),
+                    max: util::db_to_gain(0.0),
+                    factor: 0.7,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            limiter_release: FloatParam::new("Limiter Release", 50.0, time_range(1.0, 1000.0))
+                .with_value_to_string(v2s_f32_ms_then_s(1))
+                .with_string_to_value(s2v_f32_ms_then_s()),
+            loudness_match: BoolParam::new("Loudness Match", false),
+            learn: BoolParam::new("Learn", false),
+            learn_target: FloatParam::new(
+                "Learn Target",
+                3.0,
+                FloatRange::Linear {
+                    min: 0.5,
+                    max: 12.0,
+                },
+            )
+            .with_unit(" dB"),
+            state_version: RwLock::new(state::STATE_VERSION),
+            editor_state: editor::default_state(),
+            ab_slots: RwLock::new(AbSlots::default()),
         }
     }
 }
@@ -175,20 +1059,45 @@ impl Plugin for OpenMbc {
 
     // The first audio IO layout is used as the default. The other layouts may be selected either
     // explicitly or automatically by the host or the user depending on the plugin API/backend.
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
 
-        aux_input_ports: &[],
-        aux_output_ports: &[],
+            aux_input_ports: &[],
+            aux_output_ports: &[],
 
-        // Individual ports and the layout as a whole can be named here. By default these names
-        // are generated as needed. This layout will be called 'Stereo', while a layout with
-        // only one input and output channel would be called 'Mono'.
-        names: PortNames::const_default(),
-    }];
+            // Individual ports and the layout as a whole can be named here. By default these names
+            // are generated as needed. This layout will be called 'Stereo', while a layout with
+            // only one input and output channel would be called 'Mono'.
+            names: PortNames::const_default(),
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(1),
+            ..AudioIOLayout::const_default()
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(6),
+            main_output_channels: NonZeroU32::new(6),
+            names: PortNames {
+                layout: Some("5.1"),
+                ..PortNames::const_default()
+            },
+            ..AudioIOLayout::const_default()
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(8),
+            main_output_channels: NonZeroU32::new(8),
+            names: PortNames {
+                layout: Some("7.1"),
+                ..PortNames::const_default()
+            },
+            ..AudioIOLayout::const_default()
+        },
+    ];
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
 
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -200,29 +1109,47 @@ impl Plugin for OpenMbc {
     // More advanced plugins can use this to run expensive background tasks. See the field's
     // documentation for more information. `()` means that the plugin does not have any background
     // tasks.
-    type BackgroundTask = ();
+    type BackgroundTask = LearnTask;
 
     fn params(&self) -> Arc<dyn Params> {
         self.params.clone()
     }
 
+    fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        editor::create(
+            self.params.clone(),
+            self.history.clone(),
+            self.learner.clone(),
+            self.input_meter.readings(),
+            self.output_meter.readings(),
+        )
+    }
+
+    fn task_executor(&mut self) -> TaskExecutor<Self> {
+        let learner = self.learner.clone();
+        Box::new(move |task| learner.lock().unwrap().run(task))
+    }
+
+    fn filter_state(state: &mut PluginState) {
+        migration::migrate_params(&mut state.params);
+        migration::migrate_fields(&mut state.fields);
+    }
+
     fn initialize(
         &mut self,
-        _audio_io_layout: &AudioIOLayout,
-        _buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
         // Resize buffers and perform other potentially expensive initialization operations here.
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
-        self.sample_rate = _buffer_config.sample_rate;
-
-        for (idx, comp_filt) in self.comp_filt_state.iter_mut().enumerate() {
-            comp_filt.filt.bandpass(
-                self.sample_rate / self.params.comps[idx].center_freq.value(),
-                self.params.comps[idx].q.value(),
-            );
-        }
+        let channels = audio_io_layout
+            .main_input_channels
+            .map_or(0, |channels| channels.get() as usize);
+        self.setup(buffer_config.sample_rate, channels);
+        self.latency_samples = self.latency();
+        context.set_latency_samples(self.latency_samples);
 
         true
     }
@@ -230,55 +1157,486 @@ impl Plugin for OpenMbc {
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        let any_solo = self.any_solo();
+        for (mix, params) in self.band_mix.iter().zip(self.params.comps.iter()) {
+            mix.reset(params, any_solo);
+        }
+        self.band_count = self.params.band_count.value() as usize;
+        self.band_switching = false;
+        self.band_switch.reset(1.0);
+        self.reset_bands();
+        self.silent_samples = 0;
+        self.tail = 0;
+        self.skipping = false;
+        self.ducker.reset();
+        self.input_meter.reset();
+        self.match_meter.reset();
+        self.output_meter.reset();
+        self.loudness_match.reset(0.0);
+        self.learn_collector.reset();
     }
 
     fn process(
         &mut self,
         buffer: &mut Buffer,
         _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
+        // the wrapper already flushes denormals to zero around this call
+        self.update_oversampling();
+        let latency_samples = self.latency();
+        if latency_samples != self.latency_samples {
+            self.latency_samples = latency_samples;
+            context.set_latency_samples(latency_samples);
+        }
+
         //reconfigure all states
-        for (idx, comp_filt) in self.comp_filt_state.iter_mut().enumerate() {
-            comp_filt.filt.bandpass(
-                self.params.comps[idx].center_freq.value() / self.sample_rate,
-                self.params.comps[idx].q.value(),
-            );
-
-        }
-        //THIS IS STEREO!
-        for channel_samples in buffer.iter_samples() {
-            for sample in channel_samples {
-                // feed the signal to each filter seperately
-                let total = self
-                    .comp_filt_state
-                    .iter_mut()
-                    .map(|comp_filt| {
-                        // let smp = comp_filt.filt.process(*sample);
-                        comp_filt.comp.process(*sample, None)
-                    })
-                    .enumerate()
-                    .map(|(idx, smp)| {
-                        if self.params.comps[idx].enable.value() {
-                            smp * self.params.comps[idx].gain.smoothed.next()
-                                * (1.0 / MAX_MBCS as f32)
-                        } else {
-                            0.0
-                        }
-                    })
-                    .sum();
+        self.update_block(&HostTempo::from_transport(context.transport()));
+
+        if let Some(task) = self.learn_task() {
+            context.execute_background(task);
+        }
+
+        let silent = buffer
+            .as_slice_immutable()
+            .iter()
+            .all(|channel| channel.iter().all(|smp| smp.abs() < SILENCE_GAIN));
+        let skip = self.skip_silence(silent, buffer.samples());
+
+        // the bands come out late by the oversampling latency, so the envelope has to as well
+        let duck_delay = oversampling::latency_samples(self.oversampling.0, self.oversampling.1);
+
+        let num_channels = buffer.channels();
+        let mut next_event = context.next_event();
+        for (sample_id, mut channel_samples) in buffer.iter_samples().enumerate() {
+            while let Some(event) = next_event {
+                if event.timing() > sample_id as u32 {
+                    break;
+                }
+
+                if let NoteEvent::NoteOn { .. } = event {
+                    self.ducker.trigger(duck_delay as usize);
+                }
+
+                next_event = context.next_event();
+            }
+
+            if skip {
+                self.skip_frame(num_channels);
+                channel_samples.iter_mut().for_each(|smp| *smp = 0.0);
+                continue;
+            }
+
+            let mut frame = [0.0; MAX_CHANNELS];
+            for (out, sample) in frame.iter_mut().zip(channel_samples.iter_mut()) {
+                *out = *sample;
+            }
 
-                *sample = total;
+            self.process_frame(&mut frame[..num_channels]);
+            if let Some(levels) = self.learn_collector.take_finished() {
+                context.execute_background(LearnTask::Block(levels));
+            }
 
-                *sample = sample.clamp(-1.5, 1.5); //hard limit to no more than 3.5dB over
+            for (sample, out) in channel_samples.iter_mut().zip(frame.iter()) {
+                *sample = *out;
             }
         }
 
-        ProcessStatus::Normal
+        if silent {
+            ProcessStatus::Tail(self.remaining_tail())
+        } else {
+            ProcessStatus::Normal
+        }
     }
 }
 
-impl Vst3Plugin for OpenMbc {
+impl OpenMbc {
+    // sample rate dependent setup, everything that may allocate happens here. the bands are
+    // rebuilt from scratch so nothing designed for an earlier sample rate is left over, all
+    // coefficients are then derived from the new rate by `update_block()`
+    fn setup(&mut self, sample_rate: f32, channels: usize) {
+        self.sample_rate = sample_rate;
+        self.channels = channels.min(MAX_CHANNELS);
+        self.band_count = self.params.band_count.value() as usize;
+        self.band_switching = false;
+        self.band_switch.reset(1.0);
+
+        self.crossovers = std::array::from_fn(|_| Crossover::default());
+        self.comp_filt_state =
+            std::array::from_fn(|_| std::array::from_fn(|_| CompFilter::default()));
+        self.excluded_delays = std::array::from_fn(|_| DelayLine::default());
+        self.oversampling = (
+            self.params.oversampling.value(),
+            self.params.oversampling_filter.value(),
+        );
+        self.apply_oversampling();
+
+        self.limiter.initialize(self.sample_rate);
+        self.input_meter.initialize(self.sample_rate, self.channels);
+        self.learn_collector.set_sample_rate(self.sample_rate);
+        self.match_meter.initialize(self.sample_rate, self.channels);
+        self.output_meter
+            .initialize(self.sample_rate, self.channels);
+        self.update_block(&HostTempo::default());
+    }
+
+    // everything on the band path, the parts that keep running through silence aren't touched
+    fn reset_bands(&mut self) {
+        for crossover in self.crossovers.iter_mut() {
+            crossover.reset();
+        }
+        for comp_filt in self.comp_filt_state.iter_mut().flatten() {
+            comp_filt.reset();
+        }
+        for delay in self.excluded_delays.iter_mut() {
+            delay.reset();
+        }
+        self.limiter.reset();
+    }
+
+    // how long the output keeps moving after the input went silent: the latency plus the
+    // longest tail of the bands, the limiter and the ducker
+    fn tail_samples(&self) -> u32 {
+        let band_count = self.params.band_count.value() as usize;
+        let band_tail = self
+            .comp_filt_state
+            .iter()
+            .take(self.channels)
+            .flat_map(|bands| bands.iter().take(band_count))
+            .map(CompFilter::tail_msec)
+            .fold(0.0, f32::max);
+        let limiter_tail = match self.params.output_mode.value() {
+            OutputMode::Limiter => RELEASE_TAILS * self.params.limiter_release.value(),
+            OutputMode::Off | OutputMode::SoftClip => 0.0,
+        };
+        let release_samples = (band_tail.max(limiter_tail) * self.sample_rate / 1000.0).ceil();
+
+        // the ducker only moves the bands that follow it
+        let ducked = self
+            .params
+            .comps
+            .iter()
+            .take(band_count)
+            .any(|params| params.duck.value());
+        let duck_samples = if ducked {
+            self.ducker.tail_samples() as u32
+        } else {
+            0
+        };
+
+        self.latency() + (release_samples as u32).max(duck_samples)
+    }
+
+    // stands in for `process_frame()` while the bands are skipped. the meters still have to
+    // fall, the ducker and the loudness match keep moving
+    fn skip_frame(&mut self, channels: usize) {
+        let frame = [0.0; MAX_CHANNELS];
+        self.input_meter.process(&frame[..channels]);
+        self.match_meter.process(&frame[..channels]);
+        self.output_meter.process(&frame[..channels]);
+        self.ducker.next();
+        self.loudness_match.next();
+    }
+
+    // counts the silent input, once it outlasted the tail the bands can be skipped. they're
+    // reset on the way in, so nothing is left decaying in the filters
+    fn skip_silence(&mut self, silent: bool, samples: usize) -> bool {
+        if !silent {
+            self.silent_samples = 0;
+            self.skipping = false;
+            return false;
+        }
+
+        if self.silent_samples == 0 {
+            self.tail = self.tail_samples();
+        }
+        let skip = self.silent_samples >= self.tail as usize;
+        if skip && !self.skipping {
+            self.reset_bands();
+        }
+        self.skipping = skip;
+        self.silent_samples = self.silent_samples.saturating_add(samples);
+
+        skip
+    }
+
+    // what's left of the tail after the silence so far, counts down to 0
+    fn remaining_tail(&self) -> u32 {
+        (self.tail as usize).saturating_sub(self.silent_samples) as u32
+    }
+
+    // switching learn on starts over, switching it off asks for the proposal
+    fn learn_task(&mut self) -> Option<LearnTask> {
+        let learning = self.params.learn.value();
+        if learning == self.learning {
+            return None;
+        }
+
+        self.learning = learning;
+        self.learn_collector.reset();
+        Some(if learning {
+            LearnTask::Reset
+        } else {
+            LearnTask::Propose {
+                band_count: self.params.band_count.value() as usize,
+                ratios: std::array::from_fn(|idx| self.params.comps[idx].ratio.value()),
+                target_db: self.params.learn_target.value(),
+            }
+        })
+    }
+
+    // crossover frequencies in Hz, the ones in use are kept in order
+    fn crossover_hz(&self, band_count: usize) -> [f32; MAX_MBCS - 1] {
+        let mut freqs = std::array::from_fn(|idx| self.params.crossovers[idx].freq.value());
+        crossover::order_freqs(&mut freqs[..band_count - 1], FREQ_RANGE_MAX);
+
+        freqs
+    }
+
+    // rebuilds the split for the new band count while the output is faded out, then fades back
+    // in. the rest of the band settings follow with the next block
+    fn switch_band_count(&mut self) {
+        self.band_count = self.params.band_count.value() as usize;
+        self.band_switching = false;
+
+        let crossover_freqs = self
+            .crossover_hz(self.band_count)
+            .map(|freq| freq / self.sample_rate);
+        for crossover in self.crossovers.iter_mut().take(self.channels) {
+            crossover.update(&crossover_freqs, self.band_count);
+        }
+        self.band_switch.set_target(self.sample_rate, 1.0);
+    }
+
+    fn any_solo(&self) -> bool {
+        self.params
+            .comps
+            .iter()
+            .take(self.params.band_count.value() as usize)
+            .any(|comp| comp.solo.value())
+    }
+
+    // pushes the parameters down to all states, done once per block
+    fn update_block(&mut self, tempo: &HostTempo) {
+        // changing the split would click, the output fades out first and `process_frame()`
+        // switches over once it's silent
+        if !self.band_switching && self.params.band_count.value() as usize != self.band_count {
+            self.band_switching = true;
+            self.band_switch.set_target(self.sample_rate, 0.0);
+        }
+        let band_count = self.band_count;
+        let crossover_hz = self.crossover_hz(band_count);
+        let crossover_freqs = crossover_hz.map(|freq| freq / self.sample_rate);
+        let band_ranges: [(f32, f32); MAX_MBCS] = std::array::from_fn(|band| {
+            (
+                if band == 0 {
+                    FREQ_RANGE_MIN
+                } else {
+                    crossover_hz[band - 1]
+                },
+                if band + 1 >= band_count {
+                    FREQ_RANGE_MAX
+                } else {
+                    crossover_hz[band]
+                },
+            )
+        });
+        let any_solo = self.any_solo();
+        let band_sample_rate = self.sample_rate * self.oversampling.0.factor() as f32;
+
+        self.limiter.ceiling = self.params.ceiling.value();
+        self.limiter
+            .update_release(self.params.limiter_release.value());
+        for (mix, params) in self.band_mix.iter().zip(self.params.comps.iter()) {
+            mix.update(params, any_solo, self.sample_rate);
+        }
+        self.links = std::array::from_fn(|idx| self.params.channels[idx].link.value());
+        for ((crossover, bands), link) in self
+            .crossovers
+            .iter_mut()
+            .zip(self.comp_filt_state.iter_mut())
+            .zip(self.links)
+            .take(self.channels)
+        {
+            crossover.update(&crossover_freqs, band_count);
+            for ((comp_filt, params), band_range) in bands
+                .iter_mut()
+                .zip(self.params.comps.iter())
+                .zip(band_ranges)
+            {
+                comp_filt.update(params, band_sample_rate, band_range, tempo);
+                comp_filt.linked = link.group().is_some();
+            }
+        }
+
+        self.ducker.update(
+            self.sample_rate,
+            self.params.duck_depth.value(),
+            self.params.duck_attack.value(),
+            self.params.duck_hold.value(),
+            self.params.duck_release.value(),
+            self.params.duck_curve.value(),
+        );
+
+        // the short term readings only move every 100 ms, once per block is plenty
+        let input_loudness = self.input_meter.short_term();
+        let output_loudness = self.match_meter.short_term();
+        let match_db = if self.params.loudness_match.value()
+            && input_loudness.is_finite()
+            && output_loudness.is_finite()
+        {
+            (input_loudness - output_loudness).clamp(-LOUDNESS_MATCH_MAX_DB, LOUDNESS_MATCH_MAX_DB)
+                as f32
+        } else {
+            0.0
+        };
+        self.loudness_match.set_target(self.sample_rate, match_db);
+    }
+
+    // runs one frame through the whole graph in place, `frame` holds one sample per channel
+    fn process_frame(&mut self, frame: &mut [f32]) {
+        self.input_meter.process(frame);
+
+        let switch_gain = self.band_switch.next();
+        if self.band_switching && switch_gain == 0.0 {
+            self.switch_band_count();
+        }
+
+        let duck_gain = self.ducker.next();
+        let gains: [f32; MAX_MBCS] = std::array::from_fn(|idx| {
+            let duck = if self.params.comps[idx].duck.value() {
+                duck_gain
+            } else {
+                1.0
+            };
+
+            self.params.comps[idx].gain.smoothed.next() * self.band_mix[idx].level.next() * duck
+        });
+        let wets: [f32; MAX_MBCS] = std::array::from_fn(|idx| {
+            self.band_mix[idx].wet.next() * self.params.comps[idx].mix.smoothed.next()
+        });
+        let mix = self.params.mix.smoothed.next();
+
+        // all channels are split first, linked channels key on the loudest sample of their
+        // group in every band
+        let mut splits = [[0.0; MAX_MBCS]; MAX_CHANNELS];
+        let mut group_keys = [[0.0_f32; MAX_MBCS]; LINK_GROUPS];
+        for (idx, input) in frame.iter().enumerate() {
+            if self.links[idx] == LinkGroup::Excluded {
+                continue;
+            }
+            splits[idx] = self.crossovers[idx].split(*input);
+
+            if let Some(group) = self.links[idx].group() {
+                for ((group_key, band), comp_filt) in group_keys[group]
+                    .iter_mut()
+                    .zip(splits[idx])
+                    .zip(self.comp_filt_state[idx].iter())
+                {
+                    let key = if comp_filt.sc_wideband { *input } else { band };
+                    if key.abs() > group_key.abs() {
+                        *group_key = key;
+                    }
+                }
+            }
+        }
+
+        for (idx, smp) in frame.iter_mut().enumerate() {
+            let input = *smp;
+            let group = self.links[idx].group();
+            if self.links[idx] == LinkGroup::Excluded {
+                *smp = self.excluded_delays[idx].process(input);
+                continue;
+            }
+
+            // the split bands sum back to unity, bypassed bands fade to their dry split
+            // the dry bands went through the same crossover allpasses and oversampling filters
+            // as the processed ones, so their sum is the latency compensated dry signal
+            let (wet_total, dry_total) = self.comp_filt_state[idx]
+                .iter_mut()
+                .zip(splits[idx])
+                .take(self.crossovers[idx].band_count())
+                .enumerate()
+                .fold(
+                    (0.0, 0.0),
+                    |(wet_total, dry_total), (band_idx, (comp_filt, band))| {
+                        if self.learning {
+                            self.learn_collector.add(band_idx, band);
+                        }
+                        let key = group.map_or(input, |group| group_keys[group][band_idx]);
+                        let (wet, dry) = comp_filt.process(band, key);
+                        (
+                            wet_total + (dry + (wet - dry) * wets[band_idx]) * gains[band_idx],
+                            dry_total + dry,
+                        )
+                    },
+                );
+
+            *smp = (dry_total + (wet_total - dry_total) * mix) * switch_gain;
+        }
+        if self.learning {
+            let processed = self.links[..frame.len()]
+                .iter()
+                .filter(|link| **link != LinkGroup::Excluded)
+                .count();
+            self.learn_collector.end_frame(processed);
+        }
+
+        // measured before the match gain, otherwise the match would chase itself. the match goes
+        // in ahead of the output stage so it can't push the output past the ceiling
+        self.match_meter.process(frame);
+        let match_gain = util::db_to_gain_fast(self.loudness_match.next());
+        frame.iter_mut().for_each(|smp| *smp *= match_gain);
+
+        match self.params.output_mode.value() {
+            OutputMode::Off => {}
+            OutputMode::Limiter => self.limiter.process(frame),
+            OutputMode::SoftClip => frame
+                .iter_mut()
+                .for_each(|smp| *smp = output::soft_clip(*smp, self.limiter.ceiling)),
+        }
+        self.output_meter.process(frame);
+    }
+
+    // linear phase oversampling and the limiter's lookahead add latency
+    fn latency(&self) -> u32 {
+        let (factor, filter) = self.oversampling;
+        let output_latency = match self.params.output_mode.value() {
+            OutputMode::Limiter => self.limiter.latency_samples(),
+            OutputMode::Off | OutputMode::SoftClip => 0,
+        };
+
+        oversampling::latency_samples(factor, filter) + output_latency
+    }
+
+    // the new filters are swapped in and the host is told about the new latency, which makes it
+    // reset the plugin
+    fn update_oversampling(&mut self) {
+        let oversampling = (
+            self.params.oversampling.value(),
+            self.params.oversampling_filter.value(),
+        );
+        if oversampling == self.oversampling {
+            return;
+        }
+
+        self.oversampling = oversampling;
+        self.apply_oversampling();
+    }
+
+    fn apply_oversampling(&mut self) {
+        let (factor, filter) = self.oversampling;
+        for comp_filt in self.comp_filt_state.iter_mut().flatten() {
+            comp_filt.set_oversampling(factor, filter);
+        }
+        for delay in self.excluded_delays.iter_mut() {
+            delay.set_delay(oversampling::latency_samples(factor, filter) as usize);
+            delay.reset();
+        }
+    }
+}
+
+impl Vst3Plugin for OpenMbc {
     const VST3_CLASS_ID: [u8; 16] = *b"openmbc_mm123456";
 
     // And also don't forget to change these categories
@@ -287,3 +1645,816 @@ impl Vst3Plugin for OpenMbc {
 }
 
 nih_export_vst3!(OpenMbc);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nih_plug::wrapper::state::ParamValue;
+    use state::{AbSlot, Snapshot};
+    use std::collections::BTreeMap;
+    use wavers::Wav;
+
+    fn get_wave_stream(samplepath: &str) -> Vec<f32> {
+        let mut wav: Wav<f32> = Wav::from_path(samplepath).unwrap();
+
+        let samples: &[f32] = &wav.read().unwrap();
+
+        samples.to_vec()
+    }
+
+    // the host normally does this through the wrapper
+    pub(crate) fn set_param<P: Param>(param: &P, value: P::Plain) {
+        unsafe {
+            param
+                .as_ptr()
+                .set_normalized_value(param.preview_normalized(value));
+        }
+    }
+
+    // the wrapper does this on initialize, without it the smoothed values start out at 0
+    pub(crate) fn init_smoothers(params: &OpenMbcParams) {
+        for comp in params.comps.iter() {
+            comp.gain.smoothed.reset(comp.gain.value());
+            comp.mix.smoothed.reset(comp.mix.value());
+        }
+        params.mix.smoothed.reset(params.mix.value());
+    }
+
+    fn render(plugin: &mut OpenMbc, input: &[f32]) -> Vec<f32> {
+        plugin.update_block(&HostTempo::default());
+        init_smoothers(&plugin.params);
+
+        input
+            .iter()
+            .flat_map(|smp| {
+                let mut frame = [*smp, -*smp];
+                plugin.process_frame(&mut frame);
+                frame
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reset_renders_are_identical() {
+        let mut plugin = OpenMbc::default();
+        let params = plugin.params.clone();
+
+        for comp in params.comps.iter() {
+            set_param(&comp.threshold, -40.0);
+            set_param(&comp.ratio, 4.0);
+            set_param(&comp.auto_release, true);
+        }
+        set_param(&params.comps[0].gate_enable, true);
+        set_param(&params.comps[1].mode, BandMode::DynamicEq);
+        set_param(&params.comps[2].mode, BandMode::TransientShaper);
+        set_param(&params.comps[3].sc_eq_mode, SidechainEqMode::Tilt);
+        set_param(&params.comps[4].mode, BandMode::DeEsser);
+        set_param(&params.oversampling, OversamplingFactor::X4);
+        set_param(&params.oversampling_filter, OversamplingFilter::Fir);
+
+        plugin.setup(44100.0, 2);
+
+        let input = get_wave_stream("testfiles/tambourine_studio_short_loop_2_cF2.wav");
+
+        plugin.reset();
+        let first = render(&mut plugin, &input);
+        plugin.reset();
+        let second = render(&mut plugin, &input);
+
+        assert!(first.iter().any(|smp| *smp != 0.0));
+        assert!(first == second);
+    }
+
+    const FULL_RANGE: (f32, f32) = (FREQ_RANGE_MIN, FREQ_RANGE_MAX);
+
+    // steady state peak of a 1 kHz sine at `level_db` through `comp_filt`, in dB
+    fn sine_peak_db(comp_filt: &mut CompFilter, sample_rate: f32, level_db: f32) -> f32 {
+        let amplitude = util::db_to_gain(level_db);
+        let mut peak = 0.0_f32;
+        for idx in 0..sample_rate as usize {
+            let phase = std::f32::consts::TAU * (idx as f32 * 1000.0 / sample_rate).fract();
+            let smp = amplitude * phase.sin();
+            let (wet, _) = comp_filt.process(smp, smp);
+            if idx >= sample_rate as usize / 2 {
+                peak = peak.max(wet.abs());
+            }
+        }
+
+        util::gain_to_db(peak)
+    }
+
+    #[test]
+    fn dynamic_eq_above_threshold() {
+        let sample_rate = 48000.0;
+        let params = CompParams::default();
+        let mut comp_filt = CompFilter::default();
+
+        // a 1 kHz bell, far more reduction than its range
+        set_param(&params.mode, BandMode::DynamicEq);
+        set_param(&params.threshold, -30.0);
+        set_param(&params.ratio, 10.0);
+        set_param(&params.deq_range, -6.0);
+        comp_filt.update(&params, sample_rate, FULL_RANGE, &HostTempo::default());
+
+        // under the threshold the EQ stays flat, over it the bell cuts by its range
+        assert!((sine_peak_db(&mut comp_filt, sample_rate, -40.0) + 40.0).abs() < 0.1);
+        assert!((sine_peak_db(&mut comp_filt, sample_rate, -6.0) + 12.0).abs() < 0.3);
+
+        set_param(&params.deq_range, 6.0);
+        comp_filt.update(&params, sample_rate, FULL_RANGE, &HostTempo::default());
+        assert!(sine_peak_db(&mut comp_filt, sample_rate, -6.0).abs() < 0.3);
+        assert!((sine_peak_db(&mut comp_filt, sample_rate, -40.0) + 40.0).abs() < 0.1);
+
+        // a center outside the band is pulled to its nearest edge
+        set_param(&params.center_freq, 5000.0);
+        comp_filt.update(&params, sample_rate, (500.0, 1000.0), &HostTempo::default());
+        assert!(sine_peak_db(&mut comp_filt, sample_rate, -6.0) > -0.5);
+    }
+
+    #[test]
+    fn deesser_split_cancels() {
+        let sample_rate = 48000.0;
+        let params = CompParams::default();
+        let mut comp_filt = CompFilter::default();
+
+        set_param(&params.mode, BandMode::DeEsser);
+        set_param(&params.threshold, -20.0);
+        set_param(&params.ratio, 4.0);
+        comp_filt.update(&params, sample_rate, FULL_RANGE, &HostTempo::default());
+
+        let sine = |idx: usize, level_db: f32| {
+            util::db_to_gain(level_db)
+                * (std::f32::consts::TAU * 6000.0 * idx as f32 / sample_rate).sin()
+        };
+
+        // nothing reaches the threshold, the sibilant band and the rest add back up to the input
+        for idx in 0..4800 {
+            let smp = sine(idx, -40.0);
+            let (wet, _) = comp_filt.process(smp, smp);
+            assert!((wet - smp).abs() < 1e-6, "{idx}: {wet} {smp}");
+        }
+
+        // keyed on a loud wideband signal the same quiet band gets reduced
+        set_param(&params.sc_wideband, true);
+        comp_filt.update(&params, sample_rate, FULL_RANGE, &HostTempo::default());
+        let mut peak = 0.0_f32;
+        for idx in 0..4800 {
+            let (wet, _) = comp_filt.process(sine(idx, -40.0), sine(idx, -6.0));
+            if idx >= 2400 {
+                peak = peak.max(wet.abs());
+            }
+        }
+        assert!(util::gain_to_db(peak) < -46.0, "{peak}");
+    }
+
+    #[test]
+    fn half_mix_is_flat() {
+        for filter in [OversamplingFilter::Iir, OversamplingFilter::Fir] {
+            // the dry halves of both mixes have to line up with the oversampled wet ones, or the
+            // sum comb filters
+            let mut plugin = OpenMbc::default();
+            let params = plugin.params.clone();
+            set_param(&params.oversampling, OversamplingFactor::X4);
+            set_param(&params.oversampling_filter, filter);
+            set_param(&params.output_mode, OutputMode::Off);
+            set_param(&params.mix, 0.5);
+            for comp in params.comps.iter() {
+                set_param(&comp.mix, 0.5);
+            }
+            plugin.setup(48000.0, 2);
+
+            // 4800 samples at 48 kHz are whole periods for all of them
+            for freq in [100.0, 1000.0, 5000.0, 12000.0] {
+                plugin.reset();
+                let phase = |idx: usize| std::f32::consts::TAU * freq * idx as f32 / 48000.0;
+                let input: Vec<f32> = (0..9600).map(|idx| 0.1 * phase(idx).sin()).collect();
+                let output = render(&mut plugin, &input);
+
+                let (re, im) = output
+                    .chunks(2)
+                    .enumerate()
+                    .skip(4800)
+                    .fold((0.0, 0.0), |(re, im), (idx, frame)| {
+                        (re + frame[0] * phase(idx).cos(), im + frame[0] * phase(idx).sin())
+                    });
+                let level_db = util::gain_to_db((re * re + im * im).sqrt() / 2400.0);
+                assert!((level_db + 20.0).abs() < 0.1, "{filter:?} {freq}: {level_db}");
+            }
+        }
+    }
+
+    #[test]
+    fn band_count_change_fades() {
+        let mut plugin = OpenMbc::default();
+        let params = plugin.params.clone();
+        plugin.setup(48000.0, 2);
+
+        let sine = |idx: usize| 0.5 * (std::f32::consts::TAU * 1000.0 * idx as f32 / 48000.0).sin();
+        let input: Vec<f32> = (0..4800).map(sine).collect();
+        let mut output = render(&mut plugin, &input);
+
+        // the new split only goes in once the output faded out, and the fades never jump further
+        // than the sine itself does
+        set_param(&params.band_count, 3);
+        plugin.update_block(&HostTempo::default());
+        for idx in 4800..9600 {
+            let mut frame = [sine(idx), -sine(idx)];
+            plugin.process_frame(&mut frame);
+            output.extend(frame);
+        }
+
+        assert_eq!(plugin.band_count, 3);
+        assert!(output[4800 * 2..].chunks(2).any(|frame| frame == [0.0, 0.0]));
+        let max_step = output
+            .chunks(2)
+            .zip(output.chunks(2).skip(1))
+            .map(|(prev, next)| (next[0] - prev[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(max_step < 0.07, "{max_step}");
+    }
+
+    #[test]
+    fn surround_linking() {
+        let mut plugin = OpenMbc::default();
+        let params = plugin.params.clone();
+
+        for comp in params.comps.iter() {
+            set_param(&comp.threshold, -40.0);
+            set_param(&comp.ratio, 4.0);
+        }
+        set_param(&params.output_mode, OutputMode::Off);
+        set_param(&params.channels[0].link, LinkGroup::Group1);
+        set_param(&params.channels[1].link, LinkGroup::Group1);
+
+        plugin.setup(48000.0, 6);
+        plugin.reset();
+        plugin.update_block(&HostTempo::default());
+        init_smoothers(&params);
+
+        // loud front left and LFE, everything else under the threshold
+        let loud = util::db_to_gain(-10.0);
+        let quiet = util::db_to_gain(-50.0);
+        let levels = [loud, quiet, quiet, loud, quiet, 0.0];
+        let mut peaks = [0.0_f32; 6];
+        for idx in 0..48000 {
+            let sine = (std::f32::consts::TAU * (idx % 48) as f32 / 48.0).sin();
+            let input = levels.map(|level| level * sine);
+            let mut frame = input;
+            plugin.process_frame(&mut frame);
+
+            assert_eq!(frame[LFE_CHANNEL], input[LFE_CHANNEL]);
+            if idx >= 24000 {
+                for (peak, smp) in peaks.iter_mut().zip(frame) {
+                    *peak = peak.max(smp.abs());
+                }
+            }
+        }
+
+        let gain_db = |channel: usize| util::gain_to_db(peaks[channel] / levels[channel]);
+        // front right is ducked along with front left, center and surrounds are left alone
+        assert!(gain_db(0) < -15.0);
+        assert!((gain_db(1) - gain_db(0)).abs() < 1.0);
+        assert!(gain_db(2).abs() < 0.5);
+        assert!(gain_db(4).abs() < 0.5);
+        assert_eq!(peaks[5], 0.0);
+    }
+
+    // sets the values right away, the way a host would
+    struct TestGuiContext;
+
+    impl GuiContext for TestGuiContext {
+        fn plugin_api(&self) -> PluginApi {
+            PluginApi::Clap
+        }
+
+        fn request_resize(&self) -> bool {
+            false
+        }
+
+        unsafe fn raw_begin_set_parameter(&self, _param: ParamPtr) {}
+
+        unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
+            param.set_normalized_value(normalized);
+        }
+
+        unsafe fn raw_end_set_parameter(&self, _param: ParamPtr) {}
+
+        fn get_state(&self) -> PluginState {
+            PluginState {
+                version: OpenMbc::VERSION.to_string(),
+                params: BTreeMap::new(),
+                fields: BTreeMap::new(),
+            }
+        }
+
+        fn set_state(&self, _state: PluginState) {}
+    }
+
+    #[test]
+    fn learned_thresholds_are_applied() {
+        let sample_rate = 48000.0;
+        let mut plugin = OpenMbc::default();
+        let params = plugin.params.clone();
+        let learner = plugin.learner.clone();
+
+        for comp in params.comps.iter() {
+            set_param(&comp.ratio, 4.0);
+        }
+        set_param(&params.learn_target, 3.0);
+        set_param(&params.learn, true);
+
+        plugin.setup(sample_rate, 2);
+        plugin.reset();
+        plugin.update_block(&HostTempo::default());
+        init_smoothers(&params);
+        learner.lock().unwrap().run(plugin.learn_task().unwrap());
+
+        // 1 kHz sits in the 500 Hz to 2 kHz band, alternating between -10 and -20 dB every
+        // other 50 ms block
+        for idx in 0..(5.0 * sample_rate) as usize {
+            let level_db = if (idx / 4800) % 2 == 0 { -10.0 } else { -20.0 };
+            let smp = util::db_to_gain(level_db)
+                * (std::f32::consts::TAU * 1000.0 * idx as f32 / sample_rate).sin();
+            let mut frame = [smp, smp];
+            plugin.process_frame(&mut frame);
+            if let Some(levels) = plugin.learn_collector.take_finished() {
+                learner.lock().unwrap().run(LearnTask::Block(levels));
+            }
+        }
+
+        set_param(&params.learn, false);
+        learner.lock().unwrap().run(plugin.learn_task().unwrap());
+        assert!(plugin.learn_task().is_none());
+        let proposal = learner.lock().unwrap().take_proposal().unwrap();
+
+        // the band peaks about 1 dB under the input, only the louder half gets compressed:
+        // (-11 - t) * 0.75 / 2 = 3 -> t = -19
+        let threshold = proposal[2].unwrap();
+        assert!((threshold + 19.0).abs() < 1.0, "{threshold}");
+
+        // the editor applies it as one undo step
+        let context = TestGuiContext;
+        let setter = ParamSetter::new(&context);
+        let mut history = History::default();
+        learn::apply_proposal(&params, &setter, &mut history, &proposal);
+        assert!((params.comps[2].threshold.value() - threshold).abs() < 0.01);
+
+        let values = history.undo().unwrap();
+        history::apply(params.as_ref(), &setter, &values);
+        assert!(params.comps.iter().all(|comp| comp.threshold.value() == 0.0));
+    }
+
+    #[test]
+    fn loudness_match_follows_input() {
+        let sample_rate = 48000.0;
+        let mut plugin = OpenMbc::default();
+        let params = plugin.params.clone();
+
+        for comp in params.comps.iter() {
+            set_param(&comp.gain, util::db_to_gain(9.0));
+        }
+        set_param(&params.output_mode, OutputMode::Off);
+        set_param(&params.loudness_match, true);
+
+        plugin.setup(sample_rate, 2);
+        plugin.reset();
+        init_smoothers(&params);
+
+        let mut heard = LoudnessMeter::default();
+        heard.initialize(sample_rate, 2);
+        let amplitude = util::db_to_gain(-20.0);
+        for block in 0..(10.0 * sample_rate) as usize / 512 {
+            plugin.update_block(&HostTempo::default());
+            for idx in block * 512..(block + 1) * 512 {
+                let smp = amplitude * (std::f32::consts::TAU * (idx % 48) as f32 / 48.0).sin();
+                let mut frame = [smp, smp];
+                plugin.process_frame(&mut frame);
+                heard.process(&frame);
+            }
+        }
+
+        // the bands are 9 dB up, the match takes that back out and the output meter agrees with
+        // what comes out
+        let input = plugin.input_meter.short_term();
+        assert!((plugin.match_meter.short_term() - input - 9.0).abs() < 0.5);
+        assert!((heard.short_term() - input).abs() < 0.5);
+        assert!((plugin.output_meter.short_term() - heard.short_term()).abs() < 0.01);
+    }
+
+    #[test]
+    fn loudness_match_stays_under_ceiling() {
+        let sample_rate = 48000.0;
+        let mut plugin = OpenMbc::default();
+        let params = plugin.params.clone();
+
+        // the bands are 10 dB down, matching brings the -3 dBFS input back up into the limiter
+        for comp in params.comps.iter() {
+            set_param(&comp.gain, util::db_to_gain(-10.0));
+        }
+        set_param(&params.ceiling, util::db_to_gain(-6.0));
+        set_param(&params.loudness_match, true);
+
+        plugin.setup(sample_rate, 2);
+        plugin.reset();
+        init_smoothers(&params);
+
+        let amplitude = util::db_to_gain(-3.0);
+        let ceiling = params.ceiling.value();
+        let mut peak = 0.0_f32;
+        for block in 0..(10.0 * sample_rate) as usize / 512 {
+            plugin.update_block(&HostTempo::default());
+            for idx in block * 512..(block + 1) * 512 {
+                let smp = amplitude
+                    * (std::f32::consts::TAU * 1000.0 * idx as f32 / sample_rate).sin();
+                let mut frame = [smp, smp];
+                plugin.process_frame(&mut frame);
+                assert!(frame[0].abs() <= ceiling * 1.0001, "{idx}: {}", frame[0]);
+                if idx as f32 >= 9.0 * sample_rate {
+                    peak = peak.max(frame[0].abs());
+                }
+            }
+        }
+
+        assert!(plugin.loudness_match.previous_value() > 9.0);
+        assert!(peak > ceiling * 0.9, "{peak}");
+    }
+
+    #[test]
+    fn skipped_frames_keep_moving() {
+        let mut plugin = OpenMbc::default();
+        plugin.setup(48000.0, 2);
+        plugin.reset();
+
+        plugin.loudness_match.set_target(48000.0, 6.0);
+        plugin.ducker.trigger(0);
+
+        // 12 dB deep once the 2 ms attack is over
+        for _ in 0..480 {
+            plugin.skip_frame(2);
+        }
+        assert!((plugin.ducker.next() - util::db_to_gain(-12.0)).abs() < 1e-3);
+
+        for _ in 0..48000 {
+            plugin.skip_frame(2);
+        }
+        assert_eq!(plugin.ducker.next(), 1.0);
+        assert_eq!(plugin.loudness_match.previous_value(), 6.0);
+    }
+
+    #[test]
+    fn silence_skips_after_tail() {
+        let mut plugin = OpenMbc::default();
+        let params = plugin.params.clone();
+
+        // nothing to release yet, only the 10 ms hold
+        set_param(&params.output_mode, OutputMode::Off);
+
+        plugin.setup(48000.0, 2);
+        plugin.reset();
+        plugin.update_block(&HostTempo::default());
+        assert_eq!(plugin.tail_samples(), plugin.latency() + 480);
+
+        // the gate, the transient shaper, the ducker and the limiter stretch it. 20 ms hold and
+        // 100 ms release in front of the compressor
+        set_param(&params.comps[2].gate_enable, true);
+        plugin.update_block(&HostTempo::default());
+        assert_eq!(plugin.tail_samples(), plugin.latency() + 11040);
+        set_param(&params.comps[2].gate_enable, false);
+
+        set_param(&params.comps[2].mode, BandMode::TransientShaper);
+        plugin.update_block(&HostTempo::default());
+        assert_eq!(plugin.tail_samples(), plugin.latency() + 38400);
+        set_param(&params.comps[2].mode, BandMode::Compressor);
+
+        // 2 ms attack, 20 ms hold and 200 ms release
+        set_param(&params.comps[0].duck, true);
+        plugin.update_block(&HostTempo::default());
+        assert_eq!(plugin.tail_samples(), plugin.latency() + 10656);
+        set_param(&params.comps[0].duck, false);
+
+        set_param(&params.output_mode, OutputMode::Limiter);
+        set_param(&params.limiter_release, 500.0);
+        plugin.update_block(&HostTempo::default());
+        // the parameter round trip isn't exact
+        assert!(plugin.tail_samples().abs_diff(plugin.latency() + 48000) <= 1);
+
+        // silence is only skipped once it outlasted the tail, anything else starts over
+        let burst = get_wave_stream("testfiles/tambourine_studio_short_loop_2_cF2.wav");
+        render(&mut plugin, &burst);
+        let tail = plugin.tail_samples();
+        assert!(!plugin.skip_silence(false, 512));
+        assert!(!plugin.skip_silence(true, tail as usize - 1));
+        assert_eq!(plugin.remaining_tail(), 1);
+        assert!(!plugin.skip_silence(true, 512));
+        assert_eq!(plugin.remaining_tail(), 0);
+        assert!(plugin.skip_silence(true, 512));
+        assert!(plugin.skip_silence(true, 512));
+        assert!(!plugin.skip_silence(false, 512));
+        assert!(!plugin.skip_silence(true, 512));
+        assert_eq!(plugin.remaining_tail(), plugin.tail - 512);
+
+        // the bands start over from a clean state
+        let mut reference = OpenMbc::default();
+        reference.params = params.clone();
+        reference.setup(48000.0, 2);
+        reference.reset();
+        assert!(render(&mut plugin, &burst) == render(&mut reference, &burst));
+    }
+
+    #[test]
+    fn deep_reduction_settles_within_tail() {
+        let sample_rate = 48000.0;
+        let mut plugin = OpenMbc::default();
+        let params = plugin.params.clone();
+
+        // far over the threshold at 20:1, close to 40 dB of reduction
+        for comp in params.comps.iter() {
+            set_param(&comp.threshold, -40.0);
+            set_param(&comp.ratio, 20.0);
+        }
+        set_param(&params.output_mode, OutputMode::Off);
+
+        plugin.setup(sample_rate, 2);
+        plugin.reset();
+
+        let sine = |level_db: f32, len: usize| -> Vec<f32> {
+            (0..len)
+                .map(|idx| {
+                    util::db_to_gain(level_db)
+                        * (std::f32::consts::TAU * 1000.0 * idx as f32 / sample_rate).sin()
+                })
+                .collect()
+        };
+        render(&mut plugin, &sine(0.0, 24000));
+
+        // the default release lets go of 10 dB every 100 ms
+        let tail = plugin.tail_samples();
+        assert!(tail > plugin.latency() + 3 * 4800, "{tail}");
+        render(&mut plugin, &vec![0.0; tail as usize]);
+
+        // by then a quiet signal under the threshold comes out as it would from a fresh plugin
+        let mut reference = OpenMbc::default();
+        reference.params = params.clone();
+        reference.setup(sample_rate, 2);
+        reference.reset();
+
+        let quiet = sine(-60.0, 4800);
+        let energy = |output: Vec<f32>| output.iter().map(|smp| smp * smp).sum::<f32>();
+        let settled = energy(render(&mut plugin, &quiet));
+        let fresh = energy(render(&mut reference, &quiet));
+        let diff_db = 10.0 * (settled / fresh).log10();
+        assert!(diff_db.abs() < 0.1, "{diff_db}");
+    }
+
+    // time the reduction takes to get within 10% of a 30 dB step, in ms, going up and back down
+    fn step_times(plugin: &mut OpenMbc) -> (f32, f32) {
+        let sample_rate = plugin.sample_rate;
+        let to_msec = |samples: usize| samples as f32 * 1000.0 / sample_rate;
+        let comp = &mut plugin.comp_filt_state[0][0].comp;
+
+        let mut attack = 0;
+        while comp.get_reduction(1.0) < 27.0 {
+            attack += 1;
+        }
+        // let it settle at the full 30 dB before releasing
+        for _ in 0..sample_rate as usize {
+            comp.get_reduction(1.0);
+        }
+        let mut release = 0;
+        while comp.get_reduction(0.0) > 3.0 {
+            release += 1;
+        }
+
+        (to_msec(attack), to_msec(release))
+    }
+
+    // gain of every band for a sine at `freq`, in dB
+    fn band_gains(plugin: &mut OpenMbc, freq: f32) -> [f32; MAX_MBCS] {
+        // half a second is a whole number of periods at all the test frequencies
+        let len = (plugin.sample_rate / 2.0) as usize;
+        let mut input_sq = 0.0;
+        let mut band_sq = [0.0; MAX_MBCS];
+        for idx in 0..len * 2 {
+            let phase = idx as f64 * freq as f64 / plugin.sample_rate as f64;
+            let smp = (std::f64::consts::TAU * phase).sin() as f32;
+            let bands = plugin.crossovers[0].split(smp);
+            if idx >= len {
+                input_sq += (smp * smp) as f64;
+                for (sq, band) in band_sq.iter_mut().zip(bands) {
+                    *sq += (band * band) as f64;
+                }
+            }
+        }
+
+        band_sq.map(|sq| (10.0 * (sq / input_sq).log10()) as f32)
+    }
+
+    #[test]
+    fn sample_rate_independence() {
+        let mut plugin = OpenMbc::default();
+        let params = plugin.params.clone();
+
+        set_param(&params.comps[0].threshold, -40.0);
+        set_param(&params.comps[0].ratio, 4.0);
+        set_param(&params.comps[0].attack, 10.0);
+        set_param(&params.comps[0].release, 100.0);
+
+        // the same plugin goes through every rate, each setup has to start over cleanly
+        let mut reference = None;
+        for sample_rate in [44100.0, 48000.0, 96000.0, 192000.0, 44100.0] {
+            plugin.setup(sample_rate, 2);
+            plugin.reset();
+
+            // the default curve releases 10 dB per release time, 27 dB take 2.7 of them
+            let (attack, release) = step_times(&mut plugin);
+            assert!((attack - 10.0).abs() < 0.1, "{sample_rate}: {attack}");
+            assert!((release - 270.0).abs() < 0.1, "{sample_rate}: {release}");
+
+            // an LR4 crossover is 6 dB down in both its bands
+            let gains: Vec<[f32; 2]> = CROSSOVER_DEFAULTS
+                .iter()
+                .enumerate()
+                .map(|(idx, freq)| {
+                    let gains = band_gains(&mut plugin, *freq);
+                    [gains[idx], gains[idx + 1]]
+                })
+                .collect();
+            for gain in gains.iter().flatten() {
+                assert!((gain + 6.02).abs() < 0.2, "{sample_rate}: {gains:?}");
+            }
+
+            let reference = reference.get_or_insert_with(|| gains.clone());
+            for (gain, reference) in gains.iter().flatten().zip(reference.iter().flatten()) {
+                assert!((gain - reference).abs() < 0.1, "{sample_rate}: {gains:?}");
+            }
+        }
+    }
+
+    // plain values like the wrapper stores them, our enums have no IDs so they go by index
+    fn save_params(params: &OpenMbcParams) -> BTreeMap<String, ParamValue> {
+        params
+            .param_map()
+            .into_iter()
+            .map(|(id, ptr, _)| {
+                let value = unsafe {
+                    match ptr {
+                        ParamPtr::FloatParam(p) => ParamValue::F32((*p).unmodulated_plain_value()),
+                        ParamPtr::IntParam(p) => ParamValue::I32((*p).unmodulated_plain_value()),
+                        ParamPtr::BoolParam(p) => ParamValue::Bool((*p).unmodulated_plain_value()),
+                        ParamPtr::EnumParam(p) => ParamValue::I32((*p).unmodulated_plain_value()),
+                    }
+                };
+
+                (id, value)
+            })
+            .collect()
+    }
+
+    fn load_params(params: &OpenMbcParams, values: &BTreeMap<String, ParamValue>) {
+        for (id, ptr, _) in params.param_map() {
+            // `ParamValue` is untagged, whole numbers can come back from JSON as either kind
+            let normalized = unsafe {
+                match (ptr, values.get(&id)) {
+                    (ParamPtr::FloatParam(p), Some(ParamValue::F32(value))) => {
+                        (*p).preview_normalized(*value)
+                    }
+                    (ParamPtr::IntParam(p), Some(ParamValue::I32(value))) => {
+                        (*p).preview_normalized(*value)
+                    }
+                    (ParamPtr::IntParam(p), Some(ParamValue::F32(value))) => {
+                        (*p).preview_normalized(*value as i32)
+                    }
+                    (ParamPtr::BoolParam(p), Some(ParamValue::Bool(value))) => {
+                        (*p).preview_normalized(*value)
+                    }
+                    (ParamPtr::EnumParam(p), Some(ParamValue::I32(value))) => {
+                        (*p).preview_normalized(*value)
+                    }
+                    (ParamPtr::EnumParam(p), Some(ParamValue::F32(value))) => {
+                        (*p).preview_normalized(*value as i32)
+                    }
+                    _ => continue,
+                }
+            };
+            unsafe { ptr.set_normalized_value(normalized) };
+        }
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let params = OpenMbcParams::default();
+
+        set_param(&params.band_count, 3);
+        set_param(&params.comps[2].mode, BandMode::DeEsser);
+        set_param(&params.comps[2].threshold, -24.0);
+        set_param(&params.crossovers[1].freq, 800.0);
+        {
+            let mut ab_slots = params.ab_slots.write().unwrap();
+            ab_slots.active = AbSlot::B;
+            ab_slots.snapshots[0] = Some(Snapshot::from([("ratio_1".to_string(), 0.5)]));
+        }
+
+        // the editor resizes its own state, a host would have saved the new size
+        let mut fields = params.serialize_fields();
+        let mut editor_state: serde_json::Value =
+            serde_json::from_str(&fields["editor_state"]).unwrap();
+        editor_state["size"] = serde_json::json!([1200, 700]);
+        fields.insert("editor_state".to_string(), editor_state.to_string());
+
+        let state = PluginState {
+            version: OpenMbc::VERSION.to_string(),
+            params: save_params(&params),
+            fields,
+        };
+        let json = serde_json::to_string(&state).unwrap();
+        let mut state: PluginState = serde_json::from_str(&json).unwrap();
+        OpenMbc::filter_state(&mut state);
+
+        let restored = OpenMbcParams::default();
+        load_params(&restored, &state.params);
+        restored.deserialize_fields(&state.fields);
+
+        for ((id, ptr, _), (_, restored_ptr, _)) in
+            params.param_map().into_iter().zip(restored.param_map())
+        {
+            unsafe {
+                assert_eq!(
+                    ptr.unmodulated_normalized_value(),
+                    restored_ptr.unmodulated_normalized_value(),
+                    "{id}"
+                );
+            }
+        }
+        assert_eq!(
+            *restored.state_version.read().unwrap(),
+            state::STATE_VERSION
+        );
+        assert_eq!(restored.editor_state.size(), (1200, 700));
+        assert_eq!(
+            *restored.ab_slots.read().unwrap(),
+            *params.ab_slots.read().unwrap()
+        );
+    }
+
+    #[test]
+    fn v0_state_is_migrated() {
+        // saved before mute replaced enable and the thresholds moved to dB
+        let json = r#"{
+            "version": "0.1.0",
+            "params": {
+                "band_count": 3,
+                "enable_1": true,
+                "enable_2": false,
+                "threshold_1": 0.1,
+                "ratio_1": 4.0,
+                "gate_threshold_2": 0.001,
+                "lower_threshold_3": 0.01,
+                "mode_3": 3
+            },
+            "fields": {}
+        }"#;
+        let mut state: PluginState = serde_json::from_str(json).unwrap();
+        OpenMbc::filter_state(&mut state);
+
+        let restored = OpenMbcParams::default();
+        load_params(&restored, &state.params);
+        restored.deserialize_fields(&state.fields);
+
+        assert_eq!(restored.band_count.value(), 3);
+        assert!(!restored.comps[0].mute.value());
+        assert!(restored.comps[1].mute.value());
+        assert!(!restored.comps[2].mute.value());
+        assert!((restored.comps[0].threshold.value() + 20.0).abs() < 0.01);
+        assert!((restored.comps[0].ratio.value() - 4.0).abs() < 1e-4);
+        assert!((restored.comps[1].gate_threshold.value() + 60.0).abs() < 0.01);
+        assert!((restored.comps[2].lower_threshold.value() + 40.0).abs() < 0.01);
+        assert_eq!(restored.comps[2].mode.value(), BandMode::TransientShaper);
+        assert_eq!(
+            *restored.state_version.read().unwrap(),
+            state::STATE_VERSION
+        );
+    }
+
+    #[test]
+    fn unversioned_fields_are_stamped() {
+        let mut fields = BTreeMap::from([(
+            "ab_slots".to_string(),
+            r#"{"active": "B"}"#.to_string(),
+        )]);
+        migration::migrate_fields(&mut fields);
+
+        let restored = OpenMbcParams::default();
+        restored.deserialize_fields(&fields);
+
+        assert_eq!(restored.ab_slots.read().unwrap().active, AbSlot::B);
+        assert_eq!(
+            *restored.state_version.read().unwrap(),
+            state::STATE_VERSION
+        );
+        // missing fields keep their defaults
+        assert_eq!(
+            restored.editor_state.size(),
+            editor::default_state().size()
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use std::f64::consts::PI;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use atomic_float::AtomicF32;
+
+use crate::MAX_CHANNELS;
+
+// measurements advance in 100 ms blocks, the momentary and short term windows span 4 and 30 of
+// them, which also gives the 75% overlap the integrated gating asks for
+const BLOCK_MSEC: f64 = 100.0;
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+const ABSOLUTE_GATE: f64 = -70.0;
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+const RANGE_LOW_PERCENTILE: f64 = 0.10;
+const RANGE_HIGH_PERCENTILE: f64 = 0.95;
+// gated loudness is kept as a histogram from the absolute gate up to +5 LUFS in 0.1 LU steps,
+// so the integrated value and the range never need the full block history
+const BINS_PER_LU: f64 = 10.0;
+const HISTOGRAM_BINS: usize = 750;
+
+// BS.1770 weights for the L R C LFE Ls Rs (Lb Rb) surround order, the LFE isn't measured
+fn channel_weights(channels: usize) -> [f64; MAX_CHANNELS] {
+    match channels {
+        6 | 8 => [1.0, 1.0, 1.0, 0.0, 1.41, 1.41, 1.41, 1.41],
+        _ => [1.0; MAX_CHANNELS],
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn lufs_to_energy(lufs: f64) -> f64 {
+    10.0_f64.powf((lufs + 0.691) / 10.0)
+}
+
+/// Readings in LUFS and LU for the range, shared with the editor. Negative infinity until
+/// there's enough signal for a measurement.
+pub struct LoudnessReadings {
+    pub momentary: AtomicF32,
+    pub short_term: AtomicF32,
+    pub integrated: AtomicF32,
+    pub range: AtomicF32,
+}
+
+impl Default for LoudnessReadings {
+    fn default() -> Self {
+        Self {
+            momentary: AtomicF32::new(f32::NEG_INFINITY),
+            short_term: AtomicF32::new(f32::NEG_INFINITY),
+            integrated: AtomicF32::new(f32::NEG_INFINITY),
+            range: AtomicF32::new(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Section {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Section {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// BS.1770 K-weighting, the high shelf and highpass redesigned for any sample rate.
+#[derive(Debug, Default, Clone, Copy)]
+struct KWeighting {
+    shelf: Section,
+    highpass: Section,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f64) -> Self {
+        let k = (PI * 1681.974450955533 / sample_rate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10.0_f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Section {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        let k = (PI * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Section {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        Self { shelf, highpass }
+    }
+
+    fn reset(&mut self) {
+        self.shelf.z = [0.0; 2];
+        self.highpass.z = [0.0; 2];
+    }
+
+    fn process(&mut self, smp: f64) -> f64 {
+        self.highpass.process(self.shelf.process(smp))
+    }
+}
+
+struct Histogram {
+    counts: [u32; HISTOGRAM_BINS],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; HISTOGRAM_BINS],
+        }
+    }
+}
+
+impl Histogram {
+    fn bin(lufs: f64) -> usize {
+        (((lufs - ABSOLUTE_GATE) * BINS_PER_LU).max(0.0) as usize).min(HISTOGRAM_BINS - 1)
+    }
+
+    fn bin_lufs(bin: usize) -> f64 {
+        ABSOLUTE_GATE + (bin as f64 + 0.5) / BINS_PER_LU
+    }
+
+    fn add(&mut self, lufs: f64) {
+        if lufs >= ABSOLUTE_GATE {
+            self.counts[Self::bin(lufs)] += 1;
+        }
+    }
+
+    // energy mean of everything above `gate`
+    fn gated_mean(&self, gate: f64) -> Option<f64> {
+        let (energy, count) = self.counts.iter().enumerate().skip(Self::bin(gate)).fold(
+            (0.0, 0),
+            |(energy, total), (bin, count)| {
+                (
+                    energy + *count as f64 * lufs_to_energy(Self::bin_lufs(bin)),
+                    total + *count,
+                )
+            },
+        );
+
+        (count > 0).then(|| energy_to_lufs(energy / count as f64))
+    }
+
+    fn integrated(&self) -> Option<f64> {
+        let mean = self.gated_mean(ABSOLUTE_GATE)?;
+        self.gated_mean(mean + INTEGRATED_RELATIVE_GATE)
+    }
+
+    // EBU Tech 3342, the spread between the 10th and 95th percentile of the gated values
+    fn range(&self) -> Option<f64> {
+        let start = Self::bin(self.gated_mean(ABSOLUTE_GATE)? + RANGE_RELATIVE_GATE);
+        let total: u32 = self.counts[start..].iter().sum();
+        if total == 0 {
+            return None;
+        }
+
+        let percentile = |fraction: f64| {
+            let target = (fraction * total as f64) as u32;
+            let mut acc = 0;
+            for (bin, count) in self.counts.iter().enumerate().skip(start) {
+                acc += count;
+                if acc > target {
+                    return Self::bin_lufs(bin);
+                }
+            }
+            Self::bin_lufs(HISTOGRAM_BINS - 1)
+        };
+
+        Some(percentile(RANGE_HIGH_PERCENTILE) - percentile(RANGE_LOW_PERCENTILE))
+    }
+}
+
+/// EBU R128 loudness meter with momentary, short term, integrated loudness and loudness range.
+pub struct LoudnessMeter {
+    filters: [KWeighting; MAX_CHANNELS],
+    weights: [f64; MAX_CHANNELS],
+    block_len: usize,
+    block_pos: usize,
+    block_energy: f64,
+    // mean square of the last blocks, summed over the channels
+    blocks: [f64; SHORT_TERM_BLOCKS],
+    block_idx: usize,
+    blocks_filled: usize,
+    short_term: f64,
+    integrated: Histogram,
+    range: Histogram,
+    readings: Arc<LoudnessReadings>,
+}
+
+impl Default for LoudnessMeter {
+    fn default() -> Self {
+        Self {
+            filters: [KWeighting::default(); MAX_CHANNELS],
+            weights: channel_weights(2),
+            block_len: 1,
+            block_pos: 0,
+            block_energy: 0.0,
+            blocks: [0.0; SHORT_TERM_BLOCKS],
+            block_idx: 0,
+            blocks_filled: 0,
+            short_term: f64::NEG_INFINITY,
+            integrated: Histogram::default(),
+            range: Histogram::default(),
+            readings: Arc::new(LoudnessReadings::default()),
+        }
+    }
+}
+
+impl LoudnessMeter {
+    pub fn initialize(&mut self, sample_rate: f32, channels: usize) {
+        self.filters = [KWeighting::new(sample_rate as f64); MAX_CHANNELS];
+        self.weights = channel_weights(channels);
+        self.block_len = ((sample_rate as f64 * BLOCK_MSEC / 1000.0) as usize).max(1);
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.filters.iter_mut().for_each(KWeighting::reset);
+        self.block_pos = 0;
+        self.block_energy = 0.0;
+        self.blocks = [0.0; SHORT_TERM_BLOCKS];
+        self.block_idx = 0;
+        self.blocks_filled = 0;
+        self.short_term = f64::NEG_INFINITY;
+        self.integrated = Histogram::default();
+        self.range = Histogram::default();
+
+        self.readings
+            .momentary
+            .store(f32::NEG_INFINITY, Ordering::Relaxed);
+        self.readings
+            .short_term
+            .store(f32::NEG_INFINITY, Ordering::Relaxed);
+        self.readings
+            .integrated
+            .store(f32::NEG_INFINITY, Ordering::Relaxed);
+        self.readings.range.store(0.0, Ordering::Relaxed);
+    }
+
+    // the editor polls these
+    pub fn readings(&self) -> Arc<LoudnessReadings> {
+        self.readings.clone()
+    }
+
+    /// last short term loudness, updated every 100 ms
+    pub fn short_term(&self) -> f64 {
+        self.short_term
+    }
+
+    /// `frame` holds one sample per channel
+    pub fn process(&mut self, frame: &[f32]) {
+        self.block_energy += frame
+            .iter()
+            .zip(self.filters.iter_mut().zip(self.weights))
+            .map(|(smp, (filt, weight))| {
+                let weighted = filt.process(*smp as f64);
+                weight * weighted * weighted
+            })
+            .sum::<f64>();
+
+        self.block_pos += 1;
+        if self.block_pos == self.block_len {
+            self.end_block();
+        }
+    }
+
+    fn window(&self, len: usize) -> f64 {
+        if self.blocks_filled < len {
+            return f64::NEG_INFINITY;
+        }
+
+        let energy = (1..=len)
+            .map(|back| {
+                self.blocks[(self.block_idx + SHORT_TERM_BLOCKS - back) % SHORT_TERM_BLOCKS]
+            })
+            .sum::<f64>();
+        energy_to_lufs(energy / len as f64)
+    }
+
+    fn end_block(&mut self) {
+        self.blocks[self.block_idx] = self.block_energy / self.block_len as f64;
+        self.block_idx = (self.block_idx + 1) % SHORT_TERM_BLOCKS;
+        self.blocks_filled = (self.blocks_filled + 1).min(SHORT_TERM_BLOCKS);
+        self.block_pos = 0;
+        self.block_energy = 0.0;
+
+        let momentary = self.window(MOMENTARY_BLOCKS);
+        self.short_term = self.window(SHORT_TERM_BLOCKS);
+        self.integrated.add(momentary);
+        self.range.add(self.short_term);
+
+        let readings = &self.readings;
+        readings
+            .momentary
+            .store(momentary as f32, Ordering::Relaxed);
+        readings
+            .short_term
+            .store(self.short_term as f32, Ordering::Relaxed);
+        if let Some(integrated) = self.integrated.integrated() {
+            readings
+                .integrated
+                .store(integrated as f32, Ordering::Relaxed);
+        }
+        if let Some(range) = self.range.range() {
+            readings.range.store(range as f32, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nih_plug::util;
+
+    fn feed(meter: &mut LoudnessMeter, sample_rate: f32, level_db: f32, seconds: f32) {
+        let amplitude = util::db_to_gain(level_db) as f64;
+        for idx in 0..(seconds * sample_rate) as usize {
+            let phase = std::f64::consts::TAU * 1000.0 * idx as f64 / sample_rate as f64;
+            let smp = (amplitude * phase.sin()) as f32;
+            meter.process(&[smp, smp]);
+        }
+    }
+
+    fn reading(value: &AtomicF32) -> f32 {
+        value.load(Ordering::Relaxed)
+    }
+
+    // EBU Tech 3341 case 1, a -23 dBFS stereo sine reads -23 LUFS everywhere
+    #[test]
+    fn sine_reads_minus_23() {
+        for sample_rate in [44100.0, 48000.0] {
+            let mut meter = LoudnessMeter::default();
+            meter.initialize(sample_rate, 2);
+            feed(&mut meter, sample_rate, -23.0, 20.0);
+
+            let readings = meter.readings();
+            assert!((reading(&readings.momentary) + 23.0).abs() < 0.1);
+            assert!((reading(&readings.short_term) + 23.0).abs() < 0.1);
+            assert!((reading(&readings.integrated) + 23.0).abs() < 0.1);
+            assert!(reading(&readings.range) < 0.2);
+        }
+    }
+
+    // EBU Tech 3341 case 3, the quiet parts fall under the relative gate
+    #[test]
+    fn relative_gate() {
+        let mut meter = LoudnessMeter::default();
+        meter.initialize(48000.0, 2);
+        feed(&mut meter, 48000.0, -36.0, 10.0);
+        feed(&mut meter, 48000.0, -23.0, 60.0);
+        feed(&mut meter, 48000.0, -36.0, 10.0);
+
+        assert!((reading(&meter.readings().integrated) + 23.0).abs() < 0.1);
+    }
+
+    // EBU Tech 3342 case 1
+    #[test]
+    fn loudness_range() {
+        let mut meter = LoudnessMeter::default();
+        meter.initialize(48000.0, 2);
+        feed(&mut meter, 48000.0, -20.0, 20.0);
+        feed(&mut meter, 48000.0, -30.0, 20.0);
+
+        assert!((reading(&meter.readings().range) - 10.0).abs() < 1.0);
+
+        meter.reset();
+        assert_eq!(reading(&meter.readings().integrated), f32::NEG_INFINITY);
+        assert_eq!(meter.short_term(), f64::NEG_INFINITY);
+    }
+}
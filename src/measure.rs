@@ -0,0 +1,345 @@
+//! Frequency and phase response of the band split, measured through the whole graph.
+//!
+//! Every band is soloed in turn with compression out of the way, so what comes out is the
+//! crossover, the band processing path and the summing. Set `OPENMBC_PLOTS` to also draw the
+//! responses into `tmp/`.
+
+use std::f64::consts::TAU;
+
+use anyhow::Error;
+use plotters::prelude::*;
+
+use crate::output::OutputMode;
+use crate::oversampling::{OversamplingFactor, OversamplingFilter};
+use crate::tempo::HostTempo;
+use crate::tests::{init_smoothers, set_param};
+use crate::{OpenMbc, CROSSOVER_DEFAULTS, MAX_MBCS};
+
+// the lowest crossover and its allpasses have long died out by then
+const IMPULSE_LEN: usize = 16384;
+const FREQ_MIN: f64 = 20.0;
+const FREQ_MAX: f64 = 20_000.0;
+const POINTS_PER_OCTAVE: f64 = 12.0;
+// a stepped sine is settled after this, then measured over at least `SINE_PERIODS`
+const SINE_SETTLE: usize = 4096;
+const SINE_PERIODS: f64 = 10.0;
+const SINE_MIN_LEN: usize = 4096;
+// keeps silent bands plottable
+const FLOOR_DB: f64 = -120.0;
+
+/// Magnitude in dB and phase in degrees, one value per measured frequency.
+#[derive(Debug, Default, Clone)]
+pub struct Response {
+    pub magnitude_db: Vec<f64>,
+    pub phase_deg: Vec<f64>,
+}
+
+impl Response {
+    fn from_bins(bins: impl Iterator<Item = (f64, f64)>) -> Self {
+        let (magnitude_db, phase_deg) = bins
+            .map(|(re, im)| {
+                (
+                    (20.0 * re.hypot(im).log10()).max(FLOOR_DB),
+                    im.atan2(re).to_degrees(),
+                )
+            })
+            .unzip();
+
+        Self {
+            magnitude_db,
+            phase_deg,
+        }
+    }
+}
+
+/// Responses of every band on its own and of all bands summed.
+pub struct Measurement {
+    pub freqs: Vec<f64>,
+    pub bands: Vec<Response>,
+    pub sum: Response,
+}
+
+/// `POINTS_PER_OCTAVE` log spaced frequencies over the audible range.
+pub fn log_freqs() -> Vec<f64> {
+    let points = ((FREQ_MAX / FREQ_MIN).log2() * POINTS_PER_OCTAVE).round() as usize;
+
+    (0..=points)
+        .map(|idx| FREQ_MIN * (FREQ_MAX / FREQ_MIN).powf(idx as f64 / points as f64))
+        .collect()
+}
+
+/// A plugin that leaves everything but the band split alone.
+pub fn neutral_plugin(sample_rate: f32) -> OpenMbc {
+    let mut plugin = OpenMbc::default();
+    let params = plugin.params.clone();
+
+    for comp in params.comps.iter() {
+        // 1:1 never reduces, whatever the threshold
+        set_param(&comp.ratio, 1.0);
+    }
+    set_param(&params.output_mode, OutputMode::Off);
+    plugin.setup(sample_rate, 2);
+
+    plugin
+}
+
+// solos `band`, or nothing for the sum, and starts over from a clean state
+fn prepare(plugin: &mut OpenMbc, band: Option<usize>) {
+    for (idx, comp) in plugin.params.comps.iter().enumerate() {
+        set_param(&comp.solo, band == Some(idx));
+    }
+    plugin.update_block(&HostTempo::default());
+    init_smoothers(&plugin.params);
+    plugin.reset();
+}
+
+// the first channel carries the signal, the second stays silent
+fn render(plugin: &mut OpenMbc, input: impl Iterator<Item = f32>) -> Vec<f32> {
+    input
+        .map(|smp| {
+            let mut frame = [smp, 0.0];
+            plugin.process_frame(&mut frame);
+            frame[0]
+        })
+        .collect()
+}
+
+pub fn impulse_response(plugin: &mut OpenMbc, band: Option<usize>) -> Vec<f32> {
+    prepare(plugin, band);
+    render(
+        plugin,
+        (0..IMPULSE_LEN).map(|idx| if idx == 0 { 1.0 } else { 0.0 }),
+    )
+}
+
+// one bin of the (windowed) DTFT, `delay` samples are taken out of the phase
+fn dtft(signal: &[f32], window: impl Fn(usize) -> f64, freq: f64, delay: f64) -> (f64, f64) {
+    let omega = TAU * freq;
+
+    signal
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (idx, smp)| {
+            let smp = *smp as f64 * window(idx);
+            let phase = omega * (idx as f64 - delay);
+            (re + smp * phase.cos(), im - smp * phase.sin())
+        })
+}
+
+fn complex_div((a_re, a_im): (f64, f64), (b_re, b_im): (f64, f64)) -> (f64, f64) {
+    let norm = b_re * b_re + b_im * b_im;
+
+    (
+        (a_re * b_re + a_im * b_im) / norm,
+        (a_im * b_re - a_re * b_im) / norm,
+    )
+}
+
+impl Measurement {
+    /// Analyses the impulse responses of every band and the sum at `freqs`.
+    pub fn from_impulses(plugin: &mut OpenMbc, freqs: &[f64]) -> Self {
+        let sample_rate = plugin.sample_rate as f64;
+        let latency = plugin.latency() as f64;
+        let mut analyse = |band: Option<usize>| {
+            let response = impulse_response(plugin, band);
+            Response::from_bins(
+                freqs
+                    .iter()
+                    .map(|freq| dtft(&response, |_| 1.0, freq / sample_rate, latency)),
+            )
+        };
+
+        Self {
+            freqs: freqs.to_vec(),
+            bands: (0..MAX_MBCS).map(|band| analyse(Some(band))).collect(),
+            sum: analyse(None),
+        }
+    }
+
+    /// Stepped sine sweep of the sum, slower than the impulses but independent of them.
+    ///
+    /// Every sine is given time to settle and the output is compared against the input in a
+    /// Hann window, so the sine's own image doesn't leak into the result.
+    pub fn sum_from_sweep(plugin: &mut OpenMbc, freqs: &[f64]) -> Response {
+        let sample_rate = plugin.sample_rate as f64;
+        let latency = plugin.latency() as f64;
+
+        Response::from_bins(freqs.iter().map(|freq| {
+            let freq = freq / sample_rate;
+            let len = ((SINE_PERIODS / freq).ceil() as usize).max(SINE_MIN_LEN);
+            let input: Vec<f32> = (0..SINE_SETTLE + len)
+                .map(|idx| (TAU * freq * idx as f64).sin() as f32)
+                .collect();
+
+            prepare(plugin, None);
+            let output = render(plugin, input.iter().copied());
+            let hann = |idx: usize| 0.5 - 0.5 * (TAU * idx as f64 / len as f64).cos();
+
+            complex_div(
+                dtft(&output[SINE_SETTLE..], hann, freq, latency),
+                dtft(&input[SINE_SETTLE..], hann, freq, 0.0),
+            )
+        }))
+    }
+
+    pub fn draw_plot(&self, filename: &str) -> Result<(), Error> {
+        let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let (upper, lower) = root.split_vertically(384);
+
+        let mut magnitude = ChartBuilder::on(&upper)
+            .margin(5)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            .build_cartesian_2d((FREQ_MIN..FREQ_MAX).log_scale(), -60f64..6f64)?;
+        magnitude.configure_mesh().y_desc("dB").draw()?;
+
+        let mut phase = ChartBuilder::on(&lower)
+            .margin(5)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            .build_cartesian_2d((FREQ_MIN..FREQ_MAX).log_scale(), -180f64..180f64)?;
+        phase.configure_mesh().x_desc("Hz").y_desc("deg").draw()?;
+
+        let responses = self
+            .bands
+            .iter()
+            .enumerate()
+            .map(|(idx, band)| (band, Palette99::pick(idx).to_rgba()))
+            .chain(std::iter::once((&self.sum, BLACK.to_rgba())));
+        for (response, color) in responses {
+            magnitude.draw_series(LineSeries::new(
+                self.freqs
+                    .iter()
+                    .copied()
+                    .zip(response.magnitude_db.iter().copied()),
+                &color,
+            ))?;
+            phase.draw_series(LineSeries::new(
+                self.freqs
+                    .iter()
+                    .copied()
+                    .zip(response.phase_deg.iter().copied()),
+                &color,
+            ))?;
+        }
+
+        root.present()?;
+        Ok(())
+    }
+}
+
+// difference of two phases in degrees, wrapped to +-180
+fn phase_diff(a: f64, b: f64) -> f64 {
+    (a - b + 540.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const FLATNESS_DB: f64 = 0.1;
+    // the half-bands' own ripple on top
+    const OVERSAMPLED_FLATNESS_DB: f64 = FLATNESS_DB + 0.05;
+
+    fn plot(measurement: &Measurement, filename: &str) {
+        if std::env::var_os("OPENMBC_PLOTS").is_some() {
+            std::fs::create_dir_all("tmp").unwrap();
+            measurement.draw_plot(filename).unwrap();
+        }
+    }
+
+    #[test]
+    fn band_split_is_flat() {
+        let mut plugin = neutral_plugin(SAMPLE_RATE);
+        let freqs = log_freqs();
+        let measurement = Measurement::from_impulses(&mut plugin, &freqs);
+        plot(&measurement, "tmp/band_split.png");
+
+        for (freq, magnitude) in freqs.iter().zip(&measurement.sum.magnitude_db) {
+            assert!(magnitude.abs() <= FLATNESS_DB, "{freq} Hz: {magnitude} dB");
+        }
+
+        // every band carries the sum's allpass phase, otherwise they couldn't sum flat
+        for (idx, band) in measurement.bands.iter().enumerate() {
+            for (freq_idx, freq) in freqs.iter().enumerate() {
+                if band.magnitude_db[freq_idx] < -20.0 {
+                    continue;
+                }
+                let diff = phase_diff(
+                    band.phase_deg[freq_idx],
+                    measurement.sum.phase_deg[freq_idx],
+                );
+                assert!(diff.abs() < 1.0, "band {idx} at {freq} Hz: {diff} deg");
+            }
+        }
+
+        // neighbouring bands meet 6 dB down at their crossover
+        let at_crossovers = CROSSOVER_DEFAULTS.map(|freq| freq as f64);
+        let crossovers = Measurement::from_impulses(&mut plugin, &at_crossovers);
+        for (idx, freq) in at_crossovers.iter().enumerate() {
+            for band in &crossovers.bands[idx..=idx + 1] {
+                let magnitude = band.magnitude_db[idx];
+                assert!((magnitude + 6.02).abs() < 0.2, "{freq} Hz: {magnitude} dB");
+            }
+        }
+    }
+
+    #[test]
+    fn oversampled_split_is_flat() {
+        // the half-bands are flat up to 12 kHz, above that they start rolling off
+        let freqs: Vec<f64> = log_freqs()
+            .into_iter()
+            .filter(|freq| *freq <= 12_000.0)
+            .collect();
+        let reference = Measurement::from_impulses(&mut neutral_plugin(SAMPLE_RATE), &freqs);
+
+        for filter in [OversamplingFilter::Iir, OversamplingFilter::Fir] {
+            let mut plugin = neutral_plugin(SAMPLE_RATE);
+            set_param(&plugin.params.oversampling, OversamplingFactor::X4);
+            set_param(&plugin.params.oversampling_filter, filter);
+            plugin.setup(SAMPLE_RATE, 2);
+            let measurement = Measurement::from_impulses(&mut plugin, &freqs);
+            plot(&measurement, &format!("tmp/band_split_{filter:?}.png"));
+
+            for (idx, freq) in freqs.iter().enumerate() {
+                let magnitude = measurement.sum.magnitude_db[idx];
+                assert!(
+                    magnitude.abs() <= OVERSAMPLED_FLATNESS_DB,
+                    "{filter:?} {freq} Hz: {magnitude} dB"
+                );
+
+                // linear phase only adds the delay the latency takes back out
+                if filter == OversamplingFilter::Fir {
+                    let diff = phase_diff(
+                        measurement.sum.phase_deg[idx],
+                        reference.sum.phase_deg[idx],
+                    );
+                    assert!(diff.abs() < 1.0, "{freq} Hz: {diff} deg");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_matches_impulses() {
+        let mut plugin = neutral_plugin(SAMPLE_RATE);
+        // every fourth point is plenty for a cross check
+        let freqs: Vec<f64> = log_freqs().into_iter().step_by(4).collect();
+        let sweep = Measurement::sum_from_sweep(&mut plugin, &freqs);
+        let impulses = Measurement::from_impulses(&mut plugin, &freqs);
+
+        for (idx, freq) in freqs.iter().enumerate() {
+            let magnitude = sweep.magnitude_db[idx];
+            assert!(magnitude.abs() <= FLATNESS_DB, "{freq} Hz: {magnitude} dB");
+            assert!(
+                (magnitude - impulses.sum.magnitude_db[idx]).abs() < 0.01,
+                "{freq} Hz"
+            );
+
+            let diff = phase_diff(sweep.phase_deg[idx], impulses.sum.phase_deg[idx]);
+            assert!(diff.abs() < 0.5, "{freq} Hz: {diff} deg");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use nih_plug::util;
+use nih_plug::wrapper::state::ParamValue;
+
+use crate::state::STATE_VERSION;
+
+// thresholds used to be stored as linear gain, the new IDs hold decibels
+const GAIN_TO_DB_IDS: [(&str, &str); 3] = [
+    ("threshold", "threshold_db"),
+    ("lower_threshold", "lower_threshold_db"),
+    ("gate_threshold", "gate_threshold_db"),
+];
+
+/// Rewrites parameters saved by older versions to the current IDs and units. Values that were
+/// already saved under the new IDs win over migrated ones.
+pub fn migrate_params(params: &mut BTreeMap<String, ParamValue>) {
+    let ids: Vec<String> = params.keys().cloned().collect();
+
+    for id in ids {
+        // band parameters carry the band number as a suffix
+        let Some((base, band)) = id.rsplit_once('_') else {
+            continue;
+        };
+
+        let (new_id, value) = match (base, &params[&id]) {
+            // disabled bands were silent before mute existed
+            ("enable", ParamValue::Bool(enable)) => {
+                (format!("mute_{band}"), ParamValue::Bool(!enable))
+            }
+            (_, ParamValue::F32(gain)) => match GAIN_TO_DB_IDS.iter().find(|(old, _)| *old == base)
+            {
+                Some((_, new)) => (
+                    format!("{new}_{band}"),
+                    ParamValue::F32(util::gain_to_db(*gain)),
+                ),
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        params.remove(&id);
+        params.entry(new_id).or_insert(value);
+    }
+}
+
+/// Brings the persisted fields up to `STATE_VERSION`.
+pub fn migrate_fields(fields: &mut BTreeMap<String, String>) {
+    // sessions saved before the fields were versioned have no version at all
+    let version = fields
+        .get("state_version")
+        .and_then(|version| serde_json::from_str::<u32>(version).ok())
+        .unwrap_or(0);
+
+    // version 1 is the first schema, there's nothing to convert from 0 yet. later schema changes
+    // rewrite the JSON in `fields` here, one version step at a time
+    if version < STATE_VERSION {
+        fields.insert("state_version".to_string(), STATE_VERSION.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_ids_are_migrated() {
+        let mut params = BTreeMap::from([
+            ("enable_1".to_string(), ParamValue::Bool(true)),
+            ("enable_2".to_string(), ParamValue::Bool(false)),
+            (
+                "threshold_1".to_string(),
+                ParamValue::F32(util::db_to_gain(-20.0)),
+            ),
+            (
+                "gate_threshold_2".to_string(),
+                ParamValue::F32(util::db_to_gain(-60.0)),
+            ),
+            (
+                "lower_threshold_3".to_string(),
+                ParamValue::F32(util::db_to_gain(-40.0)),
+            ),
+            ("threshold_db_3".to_string(), ParamValue::F32(-12.0)),
+            ("threshold_3".to_string(), ParamValue::F32(1.0)),
+            ("freq_1".to_string(), ParamValue::F32(120.0)),
+        ]);
+
+        migrate_params(&mut params);
+
+        let f32_param = |id: &str| match params.get(id) {
+            Some(ParamValue::F32(value)) => *value,
+            other => panic!("{id} is {other:?}"),
+        };
+        assert!((f32_param("threshold_db_1") + 20.0).abs() < 1e-3);
+        assert!((f32_param("gate_threshold_db_2") + 60.0).abs() < 1e-3);
+        assert!((f32_param("lower_threshold_db_3") + 40.0).abs() < 1e-3);
+        assert_eq!(f32_param("threshold_db_3"), -12.0);
+        assert_eq!(f32_param("freq_1"), 120.0);
+
+        assert!(matches!(
+            params.get("mute_1"),
+            Some(ParamValue::Bool(false))
+        ));
+        assert!(matches!(params.get("mute_2"), Some(ParamValue::Bool(true))));
+        assert!(
+            ["enable_1", "threshold_1", "gate_threshold_2", "threshold_3"]
+                .iter()
+                .all(|id| !params.contains_key(*id))
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::VecDeque;
+
+use nih_plug::prelude::Enum;
+
+use crate::MAX_CHANNELS;
+
+const LOOKAHEAD_MSEC: f32 = 1.5;
+const OVERSAMPLE: usize = 4;
+const TAPS_PER_PHASE: usize = 8;
+// the interpolated peaks lag the input by half the filter
+const DETECTOR_DELAY: usize = TAPS_PER_PHASE / 2;
+
+/// What happens to the summed bands before they leave the plugin.
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    Off,
+    #[default]
+    Limiter,
+    #[name = "Soft Clip"]
+    SoftClip,
+}
+
+#[inline]
+pub fn soft_clip(smp: f32, ceiling: f32) -> f32 {
+    ceiling * (smp / ceiling).tanh()
+}
+
+/// 4x polyphase interpolator that estimates the inter-sample peak of a channel.
+struct TruePeakDetector {
+    history: [f32; TAPS_PER_PHASE],
+    coeffs: [[f32; TAPS_PER_PHASE]; OVERSAMPLE],
+}
+
+impl Default for TruePeakDetector {
+    fn default() -> Self {
+        // hann windowed sinc with its cutoff at the original nyquist, split into phases
+        let len = OVERSAMPLE * TAPS_PER_PHASE;
+        let center = (len - 1) as f32 / 2.0;
+        let mut coeffs = [[0.0; TAPS_PER_PHASE]; OVERSAMPLE];
+
+        for (phase, phase_coeffs) in coeffs.iter_mut().enumerate() {
+            for (tap, coeff) in phase_coeffs.iter_mut().enumerate() {
+                let idx = phase + tap * OVERSAMPLE;
+                let x = (idx as f32 - center) / OVERSAMPLE as f32;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (std::f32::consts::PI * x).sin() / (std::f32::consts::PI * x)
+                };
+                let window =
+                    0.5 - 0.5 * (2.0 * std::f32::consts::PI * idx as f32 / (len - 1) as f32).cos();
+                *coeff = sinc * window;
+            }
+
+            let sum: f32 = phase_coeffs.iter().sum();
+            phase_coeffs.iter_mut().for_each(|coeff| *coeff /= sum);
+        }
+
+        Self {
+            history: [0.0; TAPS_PER_PHASE],
+            coeffs,
+        }
+    }
+}
+
+impl TruePeakDetector {
+    fn reset(&mut self) {
+        self.history = [0.0; TAPS_PER_PHASE];
+    }
+
+    /// peak around the sample `DETECTOR_DELAY` frames back
+    fn process(&mut self, smp: f32) -> f32 {
+        self.history.copy_within(0..TAPS_PER_PHASE - 1, 1);
+        self.history[0] = smp;
+
+        self.coeffs
+            .iter()
+            .map(|phase_coeffs| {
+                phase_coeffs
+                    .iter()
+                    .zip(self.history.iter())
+                    .map(|(coeff, smp)| coeff * smp)
+                    .sum::<f32>()
+                    .abs()
+            })
+            .fold(self.history[DETECTOR_DELAY].abs(), f32::max)
+    }
+}
+
+/// Lookahead brickwall limiter with linked channels.
+///
+/// The gain each sample needs is held for the lookahead window, released exponentially and
+/// then averaged over the same window. The average reaches the required gain exactly when the
+/// sample leaves the delay line, so the attack is smooth without overshooting the ceiling.
+/// The delay line also covers the true peak detector's delay.
+/// Buffers are sized in `initialize()`, processing never allocates.
+pub struct Limiter {
+    pub ceiling: f32,
+    release_coeff: f32,
+    sample_rate: f32,
+    lookahead: usize,
+    pos: usize,
+    delay_pos: usize,
+    frame: usize,
+    envelope: f32,
+    box_sum: f64,
+    detectors: [TruePeakDetector; MAX_CHANNELS],
+    delay: Vec<[f32; MAX_CHANNELS]>,
+    // (frame, gain) with rising gains, the front is the lowest gain in the lookahead window
+    held: VecDeque<(usize, f32)>,
+    smoothed: Vec<f32>,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self {
+            ceiling: 1.0,
+            release_coeff: 0.0,
+            sample_rate: 0.0,
+            lookahead: 0,
+            pos: 0,
+            delay_pos: 0,
+            frame: 0,
+            envelope: 1.0,
+            box_sum: 0.0,
+            detectors: std::array::from_fn(|_| TruePeakDetector::default()),
+            delay: Vec::new(),
+            held: VecDeque::new(),
+            smoothed: Vec::new(),
+        }
+    }
+}
+
+impl Limiter {
+    pub fn initialize(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.lookahead = ((LOOKAHEAD_MSEC * sample_rate / 1000.0).ceil() as usize).max(2);
+
+        self.delay = vec![[0.0; MAX_CHANNELS]; self.lookahead + DETECTOR_DELAY];
+        self.held = VecDeque::with_capacity(self.lookahead);
+        self.smoothed = vec![1.0; self.lookahead];
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.pos = 0;
+        self.delay_pos = 0;
+        self.frame = 0;
+        self.envelope = 1.0;
+        self.box_sum = self.lookahead as f64;
+        self.delay.fill([0.0; MAX_CHANNELS]);
+        self.held.clear();
+        self.smoothed.fill(1.0);
+        self.detectors
+            .iter_mut()
+            .for_each(|detector| detector.reset());
+    }
+
+    pub fn latency_samples(&self) -> u32 {
+        self.delay.len().saturating_sub(1) as u32
+    }
+
+    pub fn update_release(&mut self, release_msec: f32) {
+        self.release_coeff =
+            (0.10_f32.ln() / (release_msec.max(1.0) * self.sample_rate / 1000.0)).exp();
+    }
+
+    /// limits one frame in place, `frame` holds one sample per channel
+    pub fn process(&mut self, frame: &mut [f32]) {
+        let peak = frame
+            .iter()
+            .zip(self.detectors.iter_mut())
+            .map(|(smp, detector)| detector.process(*smp))
+            .fold(0.0, f32::max);
+
+        let required = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+
+        // the expired gains go before the new one comes in, so the deque never holds more than
+        // the window and stays within its capacity
+        while let Some((added, _)) = self.held.front() {
+            if self.frame.wrapping_sub(*added) < self.lookahead {
+                break;
+            }
+            self.held.pop_front();
+        }
+        while matches!(self.held.back(), Some((_, gain)) if *gain >= required) {
+            self.held.pop_back();
+        }
+        self.held.push_back((self.frame, required));
+        let held = self.held.front().map_or(1.0, |(_, gain)| *gain);
+        self.frame = self.frame.wrapping_add(1);
+        self.envelope = if held < self.envelope {
+            held
+        } else {
+            self.release_coeff * self.envelope + (1.0 - self.release_coeff) * held
+        };
+
+        self.box_sum += self.envelope as f64 - self.smoothed[self.pos] as f64;
+        self.smoothed[self.pos] = self.envelope;
+        let gain = (self.box_sum / self.lookahead as f64) as f32;
+
+        self.pos = (self.pos + 1) % self.lookahead;
+
+        for (delayed, smp) in self.delay[self.delay_pos].iter_mut().zip(frame.iter()) {
+            *delayed = *smp;
+        }
+
+        self.delay_pos = (self.delay_pos + 1) % self.delay.len();
+        for (smp, delayed) in frame.iter_mut().zip(self.delay[self.delay_pos].iter()) {
+            *smp = delayed * gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn true_peaks_stay_under_ceiling() {
+        let mut limiter = Limiter::default();
+        limiter.initialize(48000.0);
+        limiter.update_release(50.0);
+        limiter.ceiling = 0.5;
+
+        // bursts at a quarter of the sample rate, sampled halfway between the peaks so the
+        // samples sit 3 dB under the true peak, with single sample clicks in between
+        let input: Vec<f32> = (0..48000)
+            .map(|idx| match idx % 4800 {
+                0..=479 => {
+                    2.0 * (std::f32::consts::FRAC_PI_2 * idx as f32 + std::f32::consts::FRAC_PI_4)
+                        .sin()
+                }
+                2400 => -1.5,
+                _ => 0.0,
+            })
+            .collect();
+
+        let mut meter = TruePeakDetector::default();
+        let mut true_peak = 0.0_f32;
+        for smp in input {
+            let mut frame = [smp, -smp];
+            limiter.process(&mut frame);
+            assert!(frame[0].abs() <= 0.5 * 1.0001, "{}", frame[0]);
+            true_peak = true_peak.max(meter.process(frame[0]));
+        }
+
+        assert!(true_peak <= 0.5 * 1.001, "{true_peak}");
+        assert!(true_peak > 0.45, "{true_peak}");
+    }
+
+    #[test]
+    fn held_gains_stay_within_capacity() {
+        let mut limiter = Limiter::default();
+        limiter.initialize(48000.0);
+        limiter.update_release(50.0);
+        let capacity = limiter.held.capacity();
+        let mut longest = 0;
+
+        // a decaying peak over the ceiling needs a little less reduction every sample, so
+        // nothing is dropped from the back and the window fills up
+        for idx in 0..2000 {
+            let smp = 2.0 * 0.999_f32.powi(idx);
+            let mut frame = [smp, smp];
+            limiter.process(&mut frame);
+            assert!(limiter.held.len() <= limiter.lookahead, "{idx}");
+            assert_eq!(limiter.held.capacity(), capacity, "{idx}");
+            longest = longest.max(limiter.held.len());
+        }
+        assert_eq!(longest, limiter.lookahead);
+    }
+
+    #[test]
+    fn latency_covers_detector() {
+        let mut limiter = Limiter::default();
+        limiter.initialize(48000.0);
+        limiter.update_release(50.0);
+
+        // a lone impulse under the ceiling comes out untouched after the reported latency
+        let latency = limiter.latency_samples() as usize;
+        for idx in 0..latency + 8 {
+            let mut frame = [if idx == 0 { 0.5 } else { 0.0 }; 2];
+            limiter.process(&mut frame);
+            assert_eq!(frame[0], if idx == latency { 0.5 } else { 0.0 }, "{idx}");
+        }
+    }
+}
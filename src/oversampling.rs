@@ -0,0 +1,499 @@
+use std::f64::consts::PI;
+
+use nih_plug::prelude::Enum;
+
+pub const MAX_STAGES: usize = 3;
+pub const MAX_FACTOR: usize = 1 << MAX_STAGES;
+
+const IIR_COEFS: usize = 8;
+const IIR_TRANSITION: f64 = 0.04;
+const FIR_TAPS: usize = 33;
+const FIR_CENTER: usize = FIR_TAPS / 2;
+// every stage delays by half the filter length at its own input rate
+const FIR_STAGE_LATENCY: usize = FIR_CENTER;
+
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OversamplingFactor {
+    #[default]
+    #[name = "Off"]
+    X1,
+    #[name = "2x"]
+    X2,
+    #[name = "4x"]
+    X4,
+    #[name = "8x"]
+    X8,
+}
+
+impl OversamplingFactor {
+    pub fn stages(&self) -> usize {
+        match self {
+            OversamplingFactor::X1 => 0,
+            OversamplingFactor::X2 => 1,
+            OversamplingFactor::X4 => 2,
+            OversamplingFactor::X8 => 3,
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        1 << self.stages()
+    }
+}
+
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OversamplingFilter {
+    /// polyphase allpass half-bands, next to no latency
+    #[default]
+    #[name = "Min Phase"]
+    Iir,
+    /// windowed sinc half-bands, linear phase at the cost of latency
+    #[name = "Linear Phase"]
+    Fir,
+}
+
+/// latency in samples at the base rate for a full up/down round trip
+pub fn latency_samples(factor: OversamplingFactor, filter: OversamplingFilter) -> u32 {
+    match filter {
+        OversamplingFilter::Iir => 0,
+        OversamplingFilter::Fir => (0..factor.stages())
+            .map(|stage| FIR_STAGE_LATENCY >> stage)
+            .sum::<usize>() as u32,
+    }
+}
+
+fn iir_acc_num(q: f64, order: usize, c: f64) -> f64 {
+    let mut acc = 0.0;
+    let mut sign = 1.0;
+    let mut i = 0;
+    loop {
+        let term =
+            q.powi(i * (i + 1)) * (((i * 2 + 1) as f64) * c * PI / order as f64).sin() * sign;
+        acc += term;
+        sign = -sign;
+        i += 1;
+        if term.abs() <= 1e-100 {
+            return acc;
+        }
+    }
+}
+
+fn iir_acc_den(q: f64, order: usize, c: f64) -> f64 {
+    let mut acc = 0.0;
+    let mut sign = -1.0;
+    let mut i = 1;
+    loop {
+        let term = q.powi(i * i) * ((i * 2) as f64 * c * PI / order as f64).cos() * sign;
+        acc += term;
+        sign = -sign;
+        i += 1;
+        if term.abs() <= 1e-100 {
+            return acc;
+        }
+    }
+}
+
+// elliptic half-band design for the two allpass branches, as in Laurent de Soras' HIIR
+fn design_iir(transition: f64) -> [f32; IIR_COEFS] {
+    let order = IIR_COEFS * 2 + 1;
+
+    let mut k = ((1.0 - transition * 2.0) * PI / 4.0).tan();
+    k *= k;
+    let kksqrt = (1.0 - k * k).powf(0.25);
+    let e = 0.5 * (1.0 - kksqrt) / (1.0 + kksqrt);
+    let e4 = e * e * e * e;
+    let q = e * (1.0 + e4 * (2.0 + e4 * (15.0 + 150.0 * e4)));
+
+    std::array::from_fn(|idx| {
+        let c = (idx + 1) as f64;
+        let num = iir_acc_num(q, order, c) * q.powf(0.25);
+        let den = iir_acc_den(q, order, c) + 0.5;
+        let ww = num / den;
+        let wwsq = ww * ww;
+
+        let x = ((1.0 - wwsq * k) * (1.0 - wwsq / k)).sqrt() / (1.0 + wwsq);
+        ((1.0 - x) / (1.0 + x)) as f32
+    })
+}
+
+// blackman windowed half-band, every even tap apart from the center is zero
+fn design_fir() -> [f32; FIR_TAPS] {
+    let mut taps = [0.0; FIR_TAPS];
+    taps[FIR_CENTER] = 0.5;
+
+    for offset in (1..=FIR_CENTER).step_by(2) {
+        let x = offset as f64;
+        let sinc = (PI * x / 2.0).sin() / (PI * x);
+        let n = (FIR_CENTER + offset) as f64 / (FIR_TAPS - 1) as f64;
+        let window = 0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos();
+
+        taps[FIR_CENTER + offset] = (sinc * window) as f32;
+        taps[FIR_CENTER - offset] = (sinc * window) as f32;
+    }
+
+    // the odd taps have to sum to 0.5 for unity gain at dc
+    let odd_sum: f32 = taps.iter().skip(1).step_by(2).sum();
+    taps.iter_mut()
+        .skip(1)
+        .step_by(2)
+        .for_each(|tap| *tap *= 0.5 / odd_sum);
+
+    taps
+}
+
+/// One 2x stage, keeps the state for a single direction.
+#[derive(Clone, Copy)]
+enum HalfBand {
+    Iir {
+        coefs: [f32; IIR_COEFS],
+        x: [f32; IIR_COEFS],
+        y: [f32; IIR_COEFS],
+    },
+    Fir {
+        taps: [f32; FIR_TAPS],
+        history: [f32; FIR_TAPS],
+    },
+}
+
+impl HalfBand {
+    fn new(filter: OversamplingFilter) -> Self {
+        match filter {
+            OversamplingFilter::Iir => HalfBand::Iir {
+                coefs: design_iir(IIR_TRANSITION),
+                x: [0.0; IIR_COEFS],
+                y: [0.0; IIR_COEFS],
+            },
+            OversamplingFilter::Fir => HalfBand::Fir {
+                taps: design_fir(),
+                history: [0.0; FIR_TAPS],
+            },
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            HalfBand::Iir { x, y, .. } => {
+                *x = [0.0; IIR_COEFS];
+                *y = [0.0; IIR_COEFS];
+            }
+            HalfBand::Fir { history, .. } => *history = [0.0; FIR_TAPS],
+        }
+    }
+
+    // runs both allpass branches, each one a chain of first order sections
+    fn iir_branches(
+        coefs: &[f32; IIR_COEFS],
+        x: &mut [f32; IIR_COEFS],
+        y: &mut [f32; IIR_COEFS],
+        mut spl_0: f32,
+        mut spl_1: f32,
+    ) -> (f32, f32) {
+        for cnt in (0..IIR_COEFS).step_by(2) {
+            let tmp_0 = (spl_0 - y[cnt]) * coefs[cnt] + x[cnt];
+            let tmp_1 = (spl_1 - y[cnt + 1]) * coefs[cnt + 1] + x[cnt + 1];
+            x[cnt] = spl_0;
+            x[cnt + 1] = spl_1;
+            y[cnt] = tmp_0;
+            y[cnt + 1] = tmp_1;
+            spl_0 = tmp_0;
+            spl_1 = tmp_1;
+        }
+
+        (spl_0, spl_1)
+    }
+
+    fn push(history: &mut [f32; FIR_TAPS], smp: f32) {
+        history.copy_within(0..FIR_TAPS - 1, 1);
+        history[0] = smp;
+    }
+
+    fn upsample(&mut self, smp: f32) -> (f32, f32) {
+        match self {
+            HalfBand::Iir { coefs, x, y } => Self::iir_branches(coefs, x, y, smp, smp),
+            HalfBand::Fir { taps, history } => {
+                // only the low rate history is kept, the zero stuffed samples never touch a tap
+                Self::push(history, smp);
+                let odd = taps
+                    .iter()
+                    .skip(1)
+                    .step_by(2)
+                    .zip(history.iter())
+                    .map(|(tap, smp)| tap * smp)
+                    .sum::<f32>();
+
+                (history[FIR_CENTER / 2], 2.0 * odd)
+            }
+        }
+    }
+
+    fn downsample(&mut self, smp_0: f32, smp_1: f32) -> f32 {
+        match self {
+            HalfBand::Iir { coefs, x, y } => {
+                let (spl_0, spl_1) = Self::iir_branches(coefs, x, y, smp_1, smp_0);
+                0.5 * (spl_0 + spl_1)
+            }
+            HalfBand::Fir { taps, history } => {
+                Self::push(history, smp_0);
+                let res = taps
+                    .iter()
+                    .zip(history.iter())
+                    .map(|(tap, smp)| tap * smp)
+                    .sum();
+                Self::push(history, smp_1);
+
+                res
+            }
+        }
+    }
+}
+
+/// Cascaded 2x stages around a per-sample process, all state is preallocated.
+pub struct Oversampler {
+    stages: usize,
+    up: [HalfBand; MAX_STAGES],
+    down: [HalfBand; MAX_STAGES],
+}
+
+impl Default for Oversampler {
+    fn default() -> Self {
+        Self::new(OversamplingFactor::default(), OversamplingFilter::default())
+    }
+}
+
+impl Oversampler {
+    pub fn new(factor: OversamplingFactor, filter: OversamplingFilter) -> Self {
+        Self {
+            stages: factor.stages(),
+            up: [HalfBand::new(filter); MAX_STAGES],
+            down: [HalfBand::new(filter); MAX_STAGES],
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        1 << self.stages
+    }
+
+    pub fn reset(&mut self) {
+        self.up.iter_mut().for_each(HalfBand::reset);
+        self.down.iter_mut().for_each(HalfBand::reset);
+    }
+
+    /// returns `factor()` samples at the oversampled rate, the rest of the array is unused
+    pub fn upsample(&mut self, smp: f32) -> [f32; MAX_FACTOR] {
+        let mut buf = [0.0; MAX_FACTOR];
+        let mut next = [0.0; MAX_FACTOR];
+        buf[0] = smp;
+
+        for (stage, filt) in self.up.iter_mut().take(self.stages).enumerate() {
+            for idx in 0..(1 << stage) {
+                (next[idx * 2], next[idx * 2 + 1]) = filt.upsample(buf[idx]);
+            }
+            buf = next;
+        }
+
+        buf
+    }
+
+    pub fn downsample(&mut self, buf: &mut [f32; MAX_FACTOR]) -> f32 {
+        for (stage, filt) in self.down.iter_mut().take(self.stages).enumerate().rev() {
+            for idx in 0..(1 << stage) {
+                buf[idx] = filt.downsample(buf[idx * 2], buf[idx * 2 + 1]);
+            }
+        }
+
+        buf[0]
+    }
+}
+
+/// Carries an unprocessed path along with an oversampled one. The linear phase filters only need
+/// their delay, the allpass ones shift the phase as well, so there the path takes the same round
+/// trip through the filters.
+pub struct DryPath<const N: usize> {
+    delay: DelayLine<N>,
+    round_trip: Oversampler,
+}
+
+impl<const N: usize> Default for DryPath<N> {
+    fn default() -> Self {
+        Self::new(OversamplingFactor::default(), OversamplingFilter::default())
+    }
+}
+
+impl<const N: usize> DryPath<N> {
+    pub fn new(factor: OversamplingFactor, filter: OversamplingFilter) -> Self {
+        match filter {
+            OversamplingFilter::Iir => Self {
+                delay: DelayLine::default(),
+                round_trip: Oversampler::new(factor, filter),
+            },
+            OversamplingFilter::Fir => {
+                let mut delay = DelayLine::default();
+                delay.set_delay(latency_samples(factor, filter) as usize);
+                Self {
+                    delay,
+                    round_trip: Oversampler::default(),
+                }
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.delay.reset();
+        self.round_trip.reset();
+    }
+
+    pub fn process(&mut self, smp: f32) -> f32 {
+        let mut buf = self.round_trip.upsample(self.delay.process(smp));
+        self.round_trip.downsample(&mut buf)
+    }
+}
+
+/// Fixed length delay for keeping unprocessed paths aligned with oversampled ones.
+pub struct DelayLine<const N: usize> {
+    buf: [f32; N],
+    pos: usize,
+    delay: usize,
+}
+
+impl<const N: usize> Default for DelayLine<N> {
+    fn default() -> Self {
+        Self {
+            buf: [0.0; N],
+            pos: 0,
+            delay: 0,
+        }
+    }
+}
+
+impl<const N: usize> DelayLine<N> {
+    pub fn set_delay(&mut self, delay: usize) {
+        self.delay = delay.min(N - 1);
+    }
+
+    pub fn reset(&mut self) {
+        self.buf = [0.0; N];
+        self.pos = 0;
+    }
+
+    pub fn process(&mut self, smp: f32) -> f32 {
+        if self.delay == 0 {
+            return smp;
+        }
+
+        self.buf[self.pos] = smp;
+        let out = self.buf[(self.pos + N - self.delay) % N];
+        self.pos = (self.pos + 1) % N;
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FACTORS: [OversamplingFactor; 3] = [
+        OversamplingFactor::X2,
+        OversamplingFactor::X4,
+        OversamplingFactor::X8,
+    ];
+    const FILTERS: [OversamplingFilter; 2] = [OversamplingFilter::Iir, OversamplingFilter::Fir];
+
+    fn sine(freq: f64, idx: usize) -> f32 {
+        (2.0 * PI * freq * idx as f64).sin() as f32
+    }
+
+    // level of `freq` (relative to the sample rate) in dB, for a signal of whole periods
+    fn level_db(signal: &[f32], freq: f64) -> f64 {
+        let (re, im) = signal
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (idx, smp)| {
+                let phase = 2.0 * PI * freq * idx as f64;
+                (re + *smp as f64 * phase.cos(), im + *smp as f64 * phase.sin())
+            });
+
+        20.0 * (2.0 * (re * re + im * im).sqrt() / signal.len() as f64).log10()
+    }
+
+    #[test]
+    fn round_trip_is_flat() {
+        for (factor, filter) in FACTORS
+            .into_iter()
+            .flat_map(|factor| FILTERS.map(|filter| (factor, filter)))
+        {
+            // 4800 samples at 48 kHz are whole periods for all of them
+            for freq in [100.0, 1000.0, 5000.0, 12000.0] {
+                let mut oversampler = Oversampler::new(factor, filter);
+                let output: Vec<f32> = (0..9600)
+                    .map(|idx| {
+                        let mut buf = oversampler.upsample(sine(freq / 48000.0, idx));
+                        oversampler.downsample(&mut buf)
+                    })
+                    .collect();
+
+                let level = level_db(&output[4800..], freq / 48000.0);
+                assert!(level.abs() < 0.05, "{factor:?} {filter:?} {freq}: {level}");
+            }
+        }
+    }
+
+    #[test]
+    fn alias_rejection() {
+        for filter in FILTERS {
+            // 6 kHz upsampled to 96 kHz, zero stuffing alone would leave an image at 42 kHz
+            let mut up = Oversampler::new(OversamplingFactor::X2, filter);
+            let upsampled: Vec<f32> = (0..4800)
+                .flat_map(|idx| {
+                    let buf = up.upsample(sine(6000.0 / 48000.0, idx));
+                    [buf[0], buf[1]]
+                })
+                .collect();
+            let wanted = level_db(&upsampled[4800..], 6000.0 / 96000.0);
+            let image = level_db(&upsampled[4800..], 42000.0 / 96000.0);
+            assert!(wanted.abs() < 0.05, "{filter:?}: {wanted}");
+            assert!(image < -50.0, "{filter:?}: {image}");
+
+            // 36 kHz at 96 kHz would alias to 12 kHz once it's down at 48 kHz
+            let mut down = Oversampler::new(OversamplingFactor::X2, filter);
+            let downsampled: Vec<f32> = (0..4800)
+                .map(|idx| {
+                    let mut buf = [0.0; MAX_FACTOR];
+                    buf[0] = sine(36000.0 / 96000.0, idx * 2);
+                    buf[1] = sine(36000.0 / 96000.0, idx * 2 + 1);
+                    down.downsample(&mut buf)
+                })
+                .collect();
+            let alias = level_db(&downsampled[2400..], 12000.0 / 48000.0);
+            assert!(alias < -50.0, "{filter:?}: {alias}");
+        }
+    }
+
+    #[test]
+    fn latency_matches_delay() {
+        for factor in FACTORS {
+            let mut oversampler = Oversampler::new(factor, OversamplingFilter::Fir);
+            let output: Vec<f32> = (0..64)
+                .map(|idx| {
+                    let mut buf = oversampler.upsample(if idx == 0 { 1.0 } else { 0.0 });
+                    oversampler.downsample(&mut buf)
+                })
+                .collect();
+
+            let peak = output
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+                .unwrap()
+                .0;
+            assert_eq!(
+                peak as u32,
+                latency_samples(factor, OversamplingFilter::Fir),
+                "{factor:?}"
+            );
+        }
+
+        for factor in FACTORS {
+            assert_eq!(latency_samples(factor, OversamplingFilter::Iir), 0);
+        }
+    }
+}
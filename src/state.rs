@@ -0,0 +1,26 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Bumped whenever a persisted struct changes in a way `#[serde(default)]` can't cover, the
+/// conversion for older sessions goes into `migration::migrate_fields`.
+pub const STATE_VERSION: u32 = 1;
+
+/// Normalized parameter values keyed by parameter ID.
+pub type Snapshot = BTreeMap<String, f32>;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AbSlot {
+    #[default]
+    A,
+    B,
+}
+
+/// Two parameter snapshots to compare settings, the active one follows the live parameters.
+/// The operations on them live in `history`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct AbSlots {
+    pub active: AbSlot,
+    pub snapshots: [Option<Snapshot>; 2],
+}
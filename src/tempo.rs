@@ -0,0 +1,177 @@
+use nih_plug::prelude::{Enum, Transport};
+
+const DEFAULT_BPM: f64 = 120.0;
+
+/// Tempo and meter reported by the host, falls back to 120 BPM in 4/4.
+#[derive(Debug, Clone, Copy)]
+pub struct HostTempo {
+    pub bpm: f64,
+    pub beats_per_bar: f64,
+}
+
+impl Default for HostTempo {
+    fn default() -> Self {
+        Self {
+            bpm: DEFAULT_BPM,
+            beats_per_bar: 4.0,
+        }
+    }
+}
+
+impl HostTempo {
+    pub fn from_transport(transport: &Transport) -> Self {
+        Self::from_host(
+            transport.tempo,
+            transport.time_sig_numerator,
+            transport.time_sig_denominator,
+        )
+    }
+
+    /// anything the host leaves out or reports as zero falls back to the defaults
+    pub fn from_host(
+        tempo: Option<f64>,
+        numerator: Option<i32>,
+        denominator: Option<i32>,
+    ) -> Self {
+        let beats_per_bar = match (numerator, denominator) {
+            (Some(num), Some(den)) if num > 0 && den > 0 => num as f64 * 4.0 / den as f64,
+            _ => 4.0,
+        };
+
+        Self {
+            bpm: tempo.filter(|bpm| *bpm > 0.0).unwrap_or(DEFAULT_BPM),
+            beats_per_bar,
+        }
+    }
+}
+
+/// Note length for tempo synced times, D is dotted and T is triplet.
+#[derive(Enum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteValue {
+    #[name = "1/64"]
+    N64,
+    #[name = "1/64 D"]
+    N64D,
+    #[name = "1/64 T"]
+    N64T,
+    #[name = "1/32"]
+    N32,
+    #[name = "1/32 D"]
+    N32D,
+    #[name = "1/32 T"]
+    N32T,
+    #[name = "1/16"]
+    N16,
+    #[name = "1/16 D"]
+    N16D,
+    #[name = "1/16 T"]
+    N16T,
+    #[default]
+    #[name = "1/8"]
+    N8,
+    #[name = "1/8 D"]
+    N8D,
+    #[name = "1/8 T"]
+    N8T,
+    #[name = "1/4"]
+    N4,
+    #[name = "1/4 D"]
+    N4D,
+    #[name = "1/4 T"]
+    N4T,
+    #[name = "1/2"]
+    N2,
+    #[name = "1/2 D"]
+    N2D,
+    #[name = "1/2 T"]
+    N2T,
+    #[name = "1 Bar"]
+    Bar,
+}
+
+impl NoteValue {
+    // length in quarter notes, bars are handled separately since they follow the meter
+    fn beats(&self) -> f64 {
+        match self {
+            NoteValue::N64 => 1.0 / 16.0,
+            NoteValue::N64D => 1.5 / 16.0,
+            NoteValue::N64T => 2.0 / 3.0 / 16.0,
+            NoteValue::N32 => 1.0 / 8.0,
+            NoteValue::N32D => 1.5 / 8.0,
+            NoteValue::N32T => 2.0 / 3.0 / 8.0,
+            NoteValue::N16 => 1.0 / 4.0,
+            NoteValue::N16D => 1.5 / 4.0,
+            NoteValue::N16T => 2.0 / 3.0 / 4.0,
+            NoteValue::N8 => 1.0 / 2.0,
+            NoteValue::N8D => 1.5 / 2.0,
+            NoteValue::N8T => 2.0 / 3.0 / 2.0,
+            NoteValue::N4 => 1.0,
+            NoteValue::N4D => 1.5,
+            NoteValue::N4T => 2.0 / 3.0,
+            NoteValue::N2 => 2.0,
+            NoteValue::N2D => 3.0,
+            NoteValue::N2T => 4.0 / 3.0,
+            NoteValue::Bar => 4.0,
+        }
+    }
+
+    pub fn to_msec(&self, tempo: &HostTempo) -> f32 {
+        let beats = match self {
+            NoteValue::Bar => tempo.beats_per_bar,
+            _ => self.beats(),
+        };
+
+        (beats * 60_000.0 / tempo.bpm) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_msec(note: NoteValue, tempo: &HostTempo, msec: f32) {
+        let actual = note.to_msec(tempo);
+        assert!((actual - msec).abs() < 0.01, "{note:?}: {actual} {msec}");
+    }
+
+    #[test]
+    fn note_lengths() {
+        // a quarter note lasts 500 ms at 120 BPM
+        let tempo = HostTempo::from_host(Some(120.0), Some(4), Some(4));
+        assert_msec(NoteValue::N4, &tempo, 500.0);
+        assert_msec(NoteValue::N4D, &tempo, 750.0);
+        assert_msec(NoteValue::N4T, &tempo, 333.33);
+        assert_msec(NoteValue::N8D, &tempo, 375.0);
+        assert_msec(NoteValue::N16T, &tempo, 83.33);
+        assert_msec(NoteValue::N2D, &tempo, 1500.0);
+        assert_msec(NoteValue::Bar, &tempo, 2000.0);
+
+        // bars follow the meter, 6/8 is three quarter notes long
+        let tempo = HostTempo::from_host(Some(120.0), Some(6), Some(8));
+        assert_msec(NoteValue::Bar, &tempo, 1500.0);
+        assert_msec(NoteValue::N4, &tempo, 500.0);
+
+        let tempo = HostTempo::from_host(Some(90.0), Some(3), Some(4));
+        assert_msec(NoteValue::N8T, &tempo, 222.22);
+        assert_msec(NoteValue::Bar, &tempo, 2000.0);
+    }
+
+    #[test]
+    fn falls_back_without_host_tempo() {
+        for tempo in [
+            HostTempo::from_host(None, None, None),
+            HostTempo::from_host(Some(0.0), Some(0), Some(4)),
+            HostTempo::from_host(Some(-10.0), Some(4), None),
+        ] {
+            assert_eq!(tempo.bpm, DEFAULT_BPM);
+            assert_eq!(tempo.beats_per_bar, 4.0);
+            assert_msec(NoteValue::N4, &tempo, 500.0);
+            assert_msec(NoteValue::Bar, &tempo, 2000.0);
+        }
+
+        // a missing meter doesn't throw away a valid tempo
+        let tempo = HostTempo::from_host(Some(60.0), None, None);
+        assert_msec(NoteValue::N4, &tempo, 1000.0);
+        assert_msec(NoteValue::Bar, &tempo, 4000.0);
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use nih_plug::prelude::FloatRange;
+
+/// Skewed towards the low end so every octave gets a similar share of the knob travel.
+pub fn freq_range(min: f32, max: f32) -> FloatRange {
+    FloatRange::Skewed {
+        min,
+        max,
+        factor: FloatRange::skew_factor(-2.0),
+    }
+}
+
+/// Same idea as `freq_range`, short times need more resolution than long ones.
+pub fn time_range(min: f32, max: f32) -> FloatRange {
+    FloatRange::Skewed {
+        min,
+        max,
+        factor: FloatRange::skew_factor(-1.5),
+    }
+}
+
+/// Displays milliseconds, switching to seconds from 1000 ms on.
+pub fn v2s_f32_ms_then_s(digits: usize) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        if value < 1000.0 {
+            format!("{value:.digits$} ms")
+        } else {
+            format!("{:.digits$} s", value / 1000.0, digits = digits.max(1))
+        }
+    })
+}
+
+/// Parses `100`, `100 ms` or `0.1 s` into milliseconds.
+pub fn s2v_f32_ms_then_s() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
+    Arc::new(|string| {
+        let string = string.trim().to_lowercase();
+
+        if let Some(value) = string.strip_suffix("ms") {
+            value.trim().parse().ok()
+        } else if let Some(value) = string.strip_suffix('s') {
+            value.trim().parse::<f32>().ok().map(|value| value * 1000.0)
+        } else {
+            string.parse().ok()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ms_then_s_round_trip() {
+        let to_string = v2s_f32_ms_then_s(1);
+        let to_value = s2v_f32_ms_then_s();
+
+        assert_eq!(to_string(12.34), "12.3 ms");
+        assert_eq!(to_string(2500.0), "2.5 s");
+
+        assert_eq!(to_value("12.3 ms"), Some(12.3));
+        assert_eq!(to_value("2.5 S"), Some(2500.0));
+        assert_eq!(to_value(" 40 "), Some(40.0));
+        assert_eq!(to_value("fast"), None);
+
+        for value in [0.5, 10.0, 999.0, 1000.0, 4500.0] {
+            assert_eq!(to_value(&to_string(value)), Some(value));
+        }
+    }
+}